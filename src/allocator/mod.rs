@@ -38,6 +38,23 @@ pub fn init_heap(
     Ok(())
 }
 
+/// A snapshot of how the heap is being used, in bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HeapStats {
+    pub size:   usize,
+    /// Handed out and not yet freed (rounded up to the size of their blocks).
+    pub used:   usize,
+    /// Freed, but kept in a block list for reuse.
+    pub pooled: usize,
+    /// Never handed out, or given back to the fallback allocator.
+    pub free:   usize,
+}
+
+/// Returns how the heap is currently being used.
+pub fn stats() -> HeapStats {
+    ALLOCATOR.lock().stats()
+}
+
 pub struct Locked<A> {
     inner: spin::Mutex<A>,
 }
@@ -1,7 +1,7 @@
 use alloc::alloc::{GlobalAlloc, Layout};
 use core::{mem, ptr};
 
-use super::Locked;
+use super::{HeapStats, Locked};
 
 /// An array of available block sizes.
 ///
@@ -54,6 +54,25 @@ impl PoolAllocator {
         // list heads), as they'll be lazily initialised later.
     }
 
+    pub fn stats(&self) -> HeapStats {
+        let mut pooled = 0;
+        for (head, &block_size) in self.list_heads.iter().zip(BLOCK_SIZES) {
+            let mut node = head.as_deref();
+            while let Some(current) = node {
+                pooled += block_size;
+                node = current.next.as_deref();
+            }
+        }
+
+        let heap = &self.fallback_allocator;
+        HeapStats {
+            size: heap.size(),
+            used: heap.used() - pooled,
+            pooled,
+            free: heap.free(),
+        }
+    }
+
     /// Allocates using the fallback allocator.
     fn fallback_alloc(&mut self, layout: Layout) -> *mut u8 {
         match self.fallback_allocator.allocate_first_fit(layout) {
@@ -0,0 +1,242 @@
+//! Where the output of `print!` and `println!` goes.
+//!
+//! Output is mirrored to every registered `Sink`. To begin with, that's the
+//! VGA console and COM1, so that everything printed also reaches the host's
+//! terminal when running under QEMU with `-serial stdio`. `RING` can be
+//! registered to keep the most recent output in memory as well.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::serial::SerialSink;
+use crate::sync::IrqSafeMutex;
+use crate::vga::VgaSink;
+
+/// The most sinks that can be registered at once.
+pub const MAX_SINKS: usize = 8;
+
+/// The number of bytes of output kept by `RING`.
+pub const RING_SIZE: usize = 4096;
+
+/// Keeps the most recent output in memory, once it's been registered.
+pub static RING: RingSink = RingSink::new();
+
+const VGA: &dyn Sink = &VgaSink;
+const SERIAL: &dyn Sink = &SerialSink;
+
+static SINKS: IrqSafeMutex<[Option<&'static dyn Sink>; MAX_SINKS]> =
+    IrqSafeMutex::new([
+        Some(VGA),
+        Some(SERIAL),
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+    ]);
+
+/// Somewhere that output can be sent.
+///
+/// Sinks are shared between everything that prints, so they need to do their
+/// own locking. Sinks may be written to from interrupt handlers, so any locks
+/// they take should be `IrqSafeMutex`es.
+pub trait Sink: Sync {
+    /// A short name for the sink, such as `"vga"`, which is unique among the
+    /// registered sinks.
+    fn name(&self) -> &'static str;
+
+    fn write_str(&self, s: &str);
+
+    /// Called after everything from a single `print!` has been written.
+    fn flush(&self) {}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SinkError {
+    /// A sink with the same name is already registered.
+    AlreadyRegistered,
+    /// `MAX_SINKS` sinks are already registered.
+    Full,
+}
+
+/// Starts mirroring output to a sink.
+pub fn register(sink: &'static dyn Sink) -> Result<(), SinkError> {
+    let mut sinks = SINKS.lock();
+    if sinks.iter().flatten().any(|other| other.name() == sink.name()) {
+        return Err(SinkError::AlreadyRegistered);
+    }
+    let slot = sinks.iter_mut().find(|slot| slot.is_none());
+    let slot = slot.ok_or(SinkError::Full)?;
+    *slot = Some(sink);
+    Ok(())
+}
+
+/// Stops sending output to the sink with the given name, returning false if
+/// there isn't one.
+pub fn unregister(name: &str) -> bool {
+    let mut sinks = SINKS.lock();
+    let slot = sinks
+        .iter_mut()
+        .find(|slot| slot.map_or(false, |sink| sink.name() == name));
+    match slot {
+        Some(slot) => {
+            *slot = None;
+            true
+        },
+        None => false,
+    }
+}
+
+/// Returns the names of the registered sinks.
+pub fn sinks() -> Vec<&'static str> {
+    SINKS.lock().iter().flatten().map(|sink| sink.name()).collect()
+}
+
+#[doc(hidden)]
+pub fn _print(args: fmt::Arguments) {
+    use core::fmt::Write;
+
+    struct Writer(&'static dyn Sink);
+
+    impl fmt::Write for Writer {
+        fn write_str(&mut self, s: &str) -> fmt::Result {
+            self.0.write_str(s);
+            Ok(())
+        }
+    }
+
+    // the list is copied so that sinks can print without deadlocking
+    let sinks = *SINKS.lock();
+    for &sink in sinks.iter().flatten() {
+        Writer(sink).write_fmt(args).unwrap();
+        sink.flush();
+    }
+}
+
+/// Prints to every registered console sink.
+#[macro_export]
+macro_rules! print {
+    ($($arg:tt)*) => ($crate::console::_print(format_args!($($arg)*)));
+}
+
+/// Prints to every registered console sink, appending a newline.
+#[macro_export]
+macro_rules! println {
+    () => ($crate::print!("\n"));
+    ($($arg:tt)*) => ($crate::print!("{}\n", format_args!($($arg)*)));
+}
+
+/// A sink that keeps the last `RING_SIZE` bytes written to it.
+pub struct RingSink {
+    ring: IrqSafeMutex<Ring>,
+}
+
+struct Ring {
+    bytes: [u8; RING_SIZE],
+    /// Where the next byte will be written.
+    end:   usize,
+    /// Whether the ring has been filled, so that the oldest byte is at `end`.
+    full:  bool,
+}
+
+impl RingSink {
+    pub const fn new() -> Self {
+        RingSink {
+            ring: IrqSafeMutex::new(Ring {
+                bytes: [0; RING_SIZE],
+                end:   0,
+                full:  false,
+            }),
+        }
+    }
+
+    /// Returns everything in the ring, oldest first.
+    pub fn contents(&self) -> String {
+        let ring = self.ring.lock();
+        let (older, newer) = ring.bytes.split_at(ring.end);
+        let mut bytes = Vec::with_capacity(RING_SIZE);
+        if ring.full {
+            bytes.extend_from_slice(newer);
+        }
+        bytes.extend_from_slice(older);
+        drop(ring);
+
+        // the oldest character may have been partly overwritten
+        let start = bytes.iter().position(|&b| b & 0xc0 != 0x80);
+        String::from_utf8_lossy(&bytes[start.unwrap_or(bytes.len())..])
+            .into_owned()
+    }
+
+    /// Writes formatted text all at once, so that nothing written from an
+    /// interrupt handler can end up in the middle of it.
+    pub fn write_fmt(&self, args: fmt::Arguments) {
+        let _ = fmt::Write::write_fmt(&mut *self.ring.lock(), args);
+    }
+
+    /// Throws away everything in the ring.
+    pub fn clear(&self) {
+        let mut ring = self.ring.lock();
+        ring.end = 0;
+        ring.full = false;
+    }
+}
+
+impl Default for RingSink {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Sink for RingSink {
+    fn name(&self) -> &'static str {
+        "ring"
+    }
+
+    fn write_str(&self, s: &str) {
+        self.ring.lock().push_str(s);
+    }
+}
+
+impl Ring {
+    fn push_str(&mut self, s: &str) {
+        for &byte in s.as_bytes() {
+            self.bytes[self.end] = byte;
+            self.end = (self.end + 1) % RING_SIZE;
+            self.full |= self.end == 0;
+        }
+    }
+}
+
+impl fmt::Write for Ring {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.push_str(s);
+        Ok(())
+    }
+}
+
+
+#[test_case]
+fn test_sinks() {
+    assert_eq!(register(&RING), Ok(()));
+    assert_eq!(register(&RING), Err(SinkError::AlreadyRegistered));
+    assert!(sinks().contains(&"ring"));
+    crate::println!("test_sinks output");
+    assert!(unregister("ring"));
+    assert!(!unregister("ring"));
+    crate::println!("not in the ring");
+    assert_eq!(RING.contents(), "test_sinks output\n");
+
+    // only the last RING_SIZE bytes are kept, and the oldest character was cut
+    // in half
+    RING.clear();
+    RING.write_str("é");
+    for _ in 0..RING_SIZE / 2 - 1 {
+        RING.write_str("ab");
+    }
+    RING.write_str("c");
+    let contents = RING.contents();
+    assert!(contents.starts_with("ab") && contents.ends_with("abc"));
+    assert_eq!(contents.len(), RING_SIZE - 1);
+}
@@ -0,0 +1,110 @@
+//! The Bochs VBE extensions, as implemented by QEMU's standard VGA adapter,
+//! which can switch the display into a graphics mode with a linear
+//! framebuffer.
+
+use x86_64::instructions::port::Port;
+use x86_64::PhysAddr;
+
+const INDEX_PORT: u16 = 0x01ce;
+const DATA_PORT: u16 = 0x01cf;
+
+const ID: u16 = 0x0;
+const XRES: u16 = 0x1;
+const YRES: u16 = 0x2;
+const BPP: u16 = 0x3;
+const ENABLE: u16 = 0x4;
+const VIRT_WIDTH: u16 = 0x6;
+
+/// The oldest version of the interface that supports 32 bits per pixel.
+const MIN_ID: u16 = 0xb0c2;
+const MAX_ID: u16 = 0xb0cf;
+
+const ENABLED: u16 = 0x01;
+const LFB_ENABLED: u16 = 0x40;
+
+const PCI_ADDRESS_PORT: u16 = 0xcf8;
+const PCI_DATA_PORT: u16 = 0xcfc;
+const VENDOR_ID: u16 = 0x1234;
+const DEVICE_ID: u16 = 0x1111;
+
+/// A graphics mode, with 32 bits per pixel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct Mode {
+    pub(super) width:  usize,
+    pub(super) height: usize,
+    /// The number of pixels from the start of one row to the next.
+    pub(super) stride: usize,
+}
+
+/// Returns true if the display adapter understands the Bochs VBE extensions.
+pub(super) fn detect() -> bool {
+    (MIN_ID..=MAX_ID).contains(&read(ID))
+}
+
+/// Switches into a graphics mode with the given size.
+pub(super) fn set_mode(width: usize, height: usize) -> Mode {
+    write(ENABLE, 0);
+    write(XRES, width as u16);
+    write(YRES, height as u16);
+    write(BPP, 32);
+    write(ENABLE, ENABLED | LFB_ENABLED);
+
+    Mode {
+        width:  read(XRES) as usize,
+        height: read(YRES) as usize,
+        stride: read(VIRT_WIDTH) as usize,
+    }
+}
+
+/// Goes back to whichever standard VGA mode was in use, such as text mode.
+pub(super) fn disable() {
+    write(ENABLE, 0);
+}
+
+/// Returns where the framebuffer is in physical memory, if the adapter can be
+/// found on the PCI bus.
+pub(super) fn framebuffer_address() -> Option<PhysAddr> {
+    // QEMU puts its display adapter on the first bus
+    let device = (0..32).find(|&device| {
+        let ids = pci_read(0, device, 0);
+        ids as u16 == VENDOR_ID && (ids >> 16) as u16 == DEVICE_ID
+    })?;
+
+    // the framebuffer is the first base address register, which is memory
+    // mapped, so the bottom four bits are flags
+    let bar = pci_read(0, device, 0x10) & !0xf;
+    Some(PhysAddr::new(bar as u64))
+}
+
+fn read(register: u16) -> u16 {
+    let mut index = Port::new(INDEX_PORT);
+    let mut data = Port::new(DATA_PORT);
+    unsafe {
+        index.write(register);
+        data.read()
+    }
+}
+
+fn write(register: u16, value: u16) {
+    let mut index = Port::new(INDEX_PORT);
+    let mut data = Port::new(DATA_PORT);
+    unsafe {
+        index.write(register);
+        data.write(value);
+    }
+}
+
+/// Reads a register from the configuration space of a device's first
+/// function.
+fn pci_read(bus: u8, device: u8, offset: u8) -> u32 {
+    let address = 1 << 31
+        | (bus as u32) << 16
+        | (device as u32) << 11
+        | (offset & 0xfc) as u32;
+    let mut address_port = Port::new(PCI_ADDRESS_PORT);
+    let mut data_port = Port::new(PCI_DATA_PORT);
+    unsafe {
+        address_port.write(address);
+        data_port.read()
+    }
+}
@@ -0,0 +1,73 @@
+use super::psf::Font;
+use super::{Framebuffer, Rgb};
+use crate::vga::{ScreenChar, VGAWriter, BUFFER_HEIGHT, BUFFER_WIDTH};
+
+/// The number of rows at the bottom of a character cell that the cursor
+/// covers.
+const CURSOR_HEIGHT: usize = 2;
+
+/// Draws the text of a VGA console onto the framebuffer, one character cell
+/// at a time, only redrawing the cells that have changed.
+pub(super) struct TextConsole {
+    font:   Font<'static>,
+    /// What was last drawn in each cell, or `None` if it needs to be drawn.
+    drawn:  [[Option<ScreenChar>; BUFFER_WIDTH]; BUFFER_HEIGHT],
+    /// Where the cursor was last drawn, if it was shown.
+    cursor: Option<(usize, usize)>,
+}
+
+impl TextConsole {
+    pub(super) fn new(font: Font<'static>) -> Self {
+        TextConsole {
+            font,
+            drawn: [[None; BUFFER_WIDTH]; BUFFER_HEIGHT],
+            cursor: None,
+        }
+    }
+
+    pub(super) fn draw(
+        &mut self, framebuffer: &mut Framebuffer, screen: &VGAWriter,
+    ) {
+        let cursor = if screen.cursor_visible() {
+            Some((screen.row(), screen.column().min(BUFFER_WIDTH - 1)))
+        }
+        else {
+            None
+        };
+        if cursor != self.cursor {
+            // redraw the cells the cursor has left and moved to
+            for &(row, col) in self.cursor.iter().chain(cursor.iter()) {
+                self.drawn[row][col] = None;
+            }
+            self.cursor = cursor;
+        }
+
+        for row in 0..BUFFER_HEIGHT {
+            for col in 0..BUFFER_WIDTH {
+                let c = screen.char_at(row, col);
+                if self.drawn[row][col] != Some(c) {
+                    self.draw_cell(framebuffer, row, col, c);
+                    self.drawn[row][col] = Some(c);
+                }
+            }
+        }
+    }
+
+    fn draw_cell(
+        &self, framebuffer: &mut Framebuffer, row: usize, col: usize,
+        c: ScreenChar,
+    ) {
+        let (width, height) = (self.font.width(), self.font.height());
+        let (x, y) = (col * width, row * height);
+        let (fg, bg) = (Rgb::from(c.fg), Rgb::from(c.bg));
+
+        match self.font.glyph(c.byte as usize) {
+            Some(glyph) => framebuffer.draw_glyph(&glyph, x, y, fg, bg),
+            None => framebuffer.fill_rect(x, y, width, height, bg),
+        }
+        if self.cursor == Some((row, col)) {
+            let top = y + height - CURSOR_HEIGHT;
+            framebuffer.fill_rect(x, top, width, CURSOR_HEIGHT, fg);
+        }
+    }
+}
@@ -0,0 +1,391 @@
+//! Graphics on a linear framebuffer, with the text console drawn on it.
+//!
+//! Only the Bochs VBE display adapter, which is QEMU's standard VGA, is
+//! supported. Once `init` has switched it into a graphics mode, the VGA text
+//! buffer is no longer shown, so the text of the console being shown is drawn
+//! onto the framebuffer instead, using the font in `font.psf`. That means that
+//! `print!`, `println!` and anything else that writes to the VGA consoles keep
+//! working as before.
+//!
+//! The font is the public domain "fixed" 8x13 font from X.Org, padded to 8x16
+//! and laid out in code page 437 order.
+
+mod bochs;
+mod console;
+pub mod psf;
+
+use core::task::Poll;
+
+use conquer_once::spin::OnceCell;
+use futures_util::future;
+use futures_util::task::AtomicWaker;
+use volatile::Volatile;
+use x86_64::structures::paging::mapper::MapToError;
+use x86_64::structures::paging::{
+    FrameAllocator, Mapper, Page, PageTableFlags, PhysFrame, Size4KiB,
+};
+use x86_64::VirtAddr;
+
+use self::console::TextConsole;
+use self::psf::{Font, FontError};
+use crate::interrupts;
+use crate::sync::IrqSafeMutex;
+use crate::vga::{self, Color};
+
+/// Where the framebuffer is mapped in virtual memory.
+pub const FRAMEBUFFER_START: usize = 0x_5555_5555_0000;
+
+/// The font that the console is drawn in.
+static FONT: &[u8] = include_bytes!("font.psf");
+
+static DISPLAY: OnceCell<IrqSafeMutex<Display>> = OnceCell::uninit();
+static WAKER: AtomicWaker = AtomicWaker::new();
+
+struct Display {
+    framebuffer: Framebuffer,
+    console:     TextConsole,
+}
+
+/// A colour, as its red, green and blue components.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rgb {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Rgb {
+    pub const fn new(r: u8, g: u8, b: u8) -> Self {
+        Rgb { r, g, b }
+    }
+
+    /// Returns the colour as a pixel, in the framebuffer's format.
+    fn to_pixel(self) -> u32 {
+        (self.r as u32) << 16 | (self.g as u32) << 8 | self.b as u32
+    }
+}
+
+impl From<Color> for Rgb {
+    /// Gives the colour used for it in VGA text mode.
+    fn from(color: Color) -> Self {
+        match color {
+            Color::Black => Rgb::new(0x00, 0x00, 0x00),
+            Color::Blue => Rgb::new(0x00, 0x00, 0xaa),
+            Color::Green => Rgb::new(0x00, 0xaa, 0x00),
+            Color::Cyan => Rgb::new(0x00, 0xaa, 0xaa),
+            Color::Red => Rgb::new(0xaa, 0x00, 0x00),
+            Color::Magenta => Rgb::new(0xaa, 0x00, 0xaa),
+            Color::Brown => Rgb::new(0xaa, 0x55, 0x00),
+            Color::LightGray => Rgb::new(0xaa, 0xaa, 0xaa),
+            Color::DarkGray => Rgb::new(0x55, 0x55, 0x55),
+            Color::LightBlue => Rgb::new(0x55, 0x55, 0xff),
+            Color::LightGreen => Rgb::new(0x55, 0xff, 0x55),
+            Color::LightCyan => Rgb::new(0x55, 0xff, 0xff),
+            Color::LightRed => Rgb::new(0xff, 0x55, 0x55),
+            Color::Pink => Rgb::new(0xff, 0x55, 0xff),
+            Color::Yellow => Rgb::new(0xff, 0xff, 0x55),
+            Color::White => Rgb::new(0xff, 0xff, 0xff),
+        }
+    }
+}
+
+/// A linear framebuffer with 32 bits per pixel. Anything drawn outside of it
+/// is cut off.
+pub struct Framebuffer {
+    pixels: &'static mut [Volatile<u32>],
+    width:  usize,
+    height: usize,
+    /// The number of pixels from the start of one row to the next.
+    stride: usize,
+}
+
+impl Framebuffer {
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn put_pixel(&mut self, x: usize, y: usize, color: Rgb) {
+        if x < self.width && y < self.height {
+            self.pixels[y * self.stride + x].write(color.to_pixel());
+        }
+    }
+
+    pub fn fill_rect(
+        &mut self, x: usize, y: usize, width: usize, height: usize, color: Rgb,
+    ) {
+        let pixel = color.to_pixel();
+        let (x_end, y_end) =
+            ((x + width).min(self.width), (y + height).min(self.height));
+        for y in y..y_end {
+            let row = y * self.stride;
+            for x in x..x_end {
+                self.pixels[row + x].write(pixel);
+            }
+        }
+    }
+
+    pub fn clear(&mut self, color: Rgb) {
+        self.fill_rect(0, 0, self.width, self.height, color);
+    }
+
+    /// Draws a straight line between two points, including both ends.
+    pub fn draw_line(
+        &mut self, from: (usize, usize), to: (usize, usize), color: Rgb,
+    ) {
+        // Bresenham's algorithm, which steps along whichever axis the line is
+        // longer in, and keeps track of how far it's strayed along the other
+        let (mut x, mut y) = (from.0 as isize, from.1 as isize);
+        let (x_end, y_end) = (to.0 as isize, to.1 as isize);
+        let (dx, dy) = ((x_end - x).abs(), -(y_end - y).abs());
+        let (step_x, step_y) = ((x_end - x).signum(), (y_end - y).signum());
+        let mut error = dx + dy;
+
+        loop {
+            self.put_pixel(x as usize, y as usize, color);
+            if (x, y) == (x_end, y_end) {
+                break;
+            }
+            if 2 * error >= dy {
+                error += dy;
+                x += step_x;
+            }
+            if 2 * error <= dx {
+                error += dx;
+                y += step_y;
+            }
+        }
+    }
+
+    /// Draws the outline of a rectangle.
+    pub fn draw_rect(
+        &mut self, x: usize, y: usize, width: usize, height: usize, color: Rgb,
+    ) {
+        if width == 0 || height == 0 {
+            return;
+        }
+        let (right, bottom) = (x + width - 1, y + height - 1);
+        self.draw_line((x, y), (right, y), color);
+        self.draw_line((x, bottom), (right, bottom), color);
+        self.draw_line((x, y), (x, bottom), color);
+        self.draw_line((right, y), (right, bottom), color);
+    }
+
+    /// Draws a glyph from a font with its top-left corner at the given
+    /// position, filling in its background.
+    pub fn draw_glyph(
+        &mut self, glyph: &psf::Glyph, x: usize, y: usize, fg: Rgb, bg: Rgb,
+    ) {
+        for row in 0..glyph.height() {
+            for col in 0..glyph.width() {
+                let color = if glyph.is_set(col, row) { fg } else { bg };
+                self.put_pixel(x + col, y + row, color);
+            }
+        }
+    }
+}
+
+/// The reason the framebuffer couldn't be set up.
+#[derive(Debug)]
+pub enum FramebufferError {
+    /// There's no supported display adapter.
+    NotFound,
+    AlreadyInitialized,
+    /// The display adapter wouldn't switch to the mode that was asked for.
+    ModeNotSet,
+    Font(FontError),
+    Mapping(MapToError<Size4KiB>),
+}
+
+/// Switches the display into a graphics mode, if it's supported, and starts
+/// drawing the console onto it.
+///
+/// The mode is just big enough to fit the console in the font. Run `redraw`
+/// to keep the screen up to date with the console after that.
+pub fn init(
+    mapper: &mut impl Mapper<Size4KiB>,
+    frame_allocator: &mut impl FrameAllocator<Size4KiB>,
+) -> Result<(), FramebufferError> {
+    if DISPLAY.is_initialized() {
+        return Err(FramebufferError::AlreadyInitialized);
+    }
+    if !bochs::detect() {
+        return Err(FramebufferError::NotFound);
+    }
+    let address =
+        bochs::framebuffer_address().ok_or(FramebufferError::NotFound)?;
+    let font = Font::parse(FONT).map_err(FramebufferError::Font)?;
+
+    let width = font.width() * vga::BUFFER_WIDTH;
+    let height = font.height() * vga::BUFFER_HEIGHT;
+    let mode = bochs::set_mode(width, height);
+    if (mode.width, mode.height) != (width, height) || mode.stride < width {
+        bochs::disable();
+        return Err(FramebufferError::ModeNotSet);
+    }
+
+    let size = mode.stride * mode.height * 4;
+    let pages = {
+        let start = VirtAddr::new(FRAMEBUFFER_START as u64);
+        let end = start + size - 1u64;
+        Page::range_inclusive(
+            Page::<Size4KiB>::containing_address(start),
+            Page::containing_address(end),
+        )
+    };
+    let first_frame = PhysFrame::<Size4KiB>::containing_address(address);
+    let flags = PageTableFlags::PRESENT
+        | PageTableFlags::WRITABLE
+        | PageTableFlags::NO_CACHE;
+    for (i, page) in pages.enumerate() {
+        let frame = first_frame + i as u64;
+        let mapping =
+            unsafe { mapper.map_to(page, frame, flags, frame_allocator) };
+        match mapping {
+            Ok(flush) => flush.flush(),
+            Err(err) => {
+                bochs::disable();
+                return Err(FramebufferError::Mapping(err));
+            },
+        }
+    }
+
+    // the framebuffer was just mapped, and nothing else uses that memory
+    let pixels = unsafe {
+        core::slice::from_raw_parts_mut(
+            FRAMEBUFFER_START as *mut Volatile<u32>,
+            mode.stride * mode.height,
+        )
+    };
+    let mut framebuffer =
+        Framebuffer { pixels, width, height, stride: mode.stride };
+    framebuffer.clear(Rgb::from(Color::Black));
+
+    vga::detach_screen();
+    DISPLAY.init_once(|| {
+        IrqSafeMutex::new(Display {
+            framebuffer,
+            console: TextConsole::new(font),
+        })
+    });
+    refresh();
+    Ok(())
+}
+
+/// Returns true if the framebuffer is being used.
+pub fn is_enabled() -> bool {
+    DISPLAY.is_initialized()
+}
+
+/// Gives access to the framebuffer, if it's being used.
+///
+/// Anything drawn where there's text may be drawn over when that text
+/// changes.
+pub fn with_framebuffer<F: FnOnce(&mut Framebuffer) -> R, R>(
+    f: F,
+) -> Option<R> {
+    let display = DISPLAY.try_get().ok()?;
+    Some(f(&mut display.lock().framebuffer))
+}
+
+/// Draws any of the console that's changed since it was last drawn.
+pub fn refresh() {
+    if let Ok(display) = DISPLAY.try_get() {
+        let mut display = display.lock();
+        let Display { framebuffer, console } = &mut *display;
+        vga::with_screen(|screen| console.draw(framebuffer, screen));
+    }
+}
+
+/// Called by the timer interrupt.
+pub(crate) fn on_timer_tick() {
+    WAKER.wake();
+}
+
+/// Keeps the framebuffer up to date with the console, checking for changes on
+/// every timer tick. Does nothing if the framebuffer isn't being used.
+pub async fn redraw() {
+    if !is_enabled() {
+        return;
+    }
+
+    let mut last_tick = interrupts::ticks();
+    loop {
+        let seen = last_tick;
+        last_tick = future::poll_fn(move |cx| {
+            WAKER.register(cx.waker());
+            match interrupts::ticks() {
+                tick if tick != seen => Poll::Ready(tick),
+                _ => Poll::Pending,
+            }
+        })
+        .await;
+        refresh();
+    }
+}
+
+
+#[test_case]
+fn test_drawing() {
+    use alloc::boxed::Box;
+    use alloc::vec::Vec;
+
+    // a row is padded with two pixels that are off the edge
+    const WIDTH: usize = 8;
+    const HEIGHT: usize = 6;
+    const STRIDE: usize = 10;
+
+    /// Returns every pixel that's been drawn on, including any in the padding.
+    fn drawn(framebuffer: &Framebuffer) -> Vec<(usize, usize)> {
+        let pixels = (0..HEIGHT).flat_map(|y| (0..STRIDE).map(move |x| (x, y)));
+        pixels
+            .filter(|&(x, y)| framebuffer.pixels[y * STRIDE + x].read() != 0)
+            .collect()
+    }
+
+    let pixels: Vec<_> =
+        (0..STRIDE * HEIGHT).map(|_| Volatile::new(0)).collect();
+    let mut framebuffer = Framebuffer {
+        pixels: Box::leak(pixels.into_boxed_slice()),
+        width:  WIDTH,
+        height: HEIGHT,
+        stride: STRIDE,
+    };
+    let (white, black) = (Rgb::new(0xff, 0xff, 0xff), Rgb::new(0, 0, 0));
+
+    // cut off at the bottom-right corner
+    framebuffer.fill_rect(6, 4, 10, 10, white);
+    assert_eq!(drawn(&framebuffer), [(6, 4), (7, 4), (6, 5), (7, 5)]);
+    assert_eq!(framebuffer.pixels[4 * STRIDE + 6].read(), 0x00ff_ffff);
+
+    framebuffer.clear(black);
+    framebuffer.draw_line((0, 0), (3, 3), white);
+    assert_eq!(drawn(&framebuffer), [(0, 0), (1, 1), (2, 2), (3, 3)]);
+
+    framebuffer.clear(black);
+    framebuffer.draw_line((3, 1), (0, 1), white);
+    assert_eq!(drawn(&framebuffer), [(0, 1), (1, 1), (2, 1), (3, 1)]);
+
+    framebuffer.clear(black);
+    framebuffer.draw_rect(1, 1, 4, 3, white);
+    assert_eq!(drawn(&framebuffer), [
+        (1, 1),
+        (2, 1),
+        (3, 1),
+        (4, 1),
+        (1, 2),
+        (4, 2),
+        (1, 3),
+        (2, 3),
+        (3, 3),
+        (4, 3)
+    ]);
+
+    framebuffer.clear(black);
+    framebuffer.draw_rect(0, 0, 0, 5, white);
+    framebuffer.draw_rect(7, 5, 3, 3, white);
+    assert_eq!(drawn(&framebuffer), [(7, 5)]);
+}
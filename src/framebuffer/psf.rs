@@ -0,0 +1,165 @@
+//! PC Screen Font (PSF) bitmap fonts, as used by the Linux console.
+//!
+//! Both versions of the format are understood. Any Unicode table in the file
+//! is ignored, so glyphs are looked up by their index in the font.
+
+const PSF1_MAGIC: [u8; 2] = [0x36, 0x04];
+const PSF1_HEADER_SIZE: usize = 4;
+/// Set in a version 1 font's mode if it has 512 glyphs rather than 256.
+const PSF1_MODE_512: u8 = 0x01;
+
+const PSF2_MAGIC: [u8; 4] = [0x72, 0xb5, 0x4a, 0x86];
+const PSF2_HEADER_SIZE: usize = 32;
+
+/// The reason a font couldn't be read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FontError {
+    /// The data doesn't start with either version's magic number.
+    BadMagic,
+    /// The data ends before the last glyph does.
+    Truncated,
+    /// The header describes glyphs with no pixels, or is otherwise invalid.
+    BadHeader,
+}
+
+/// A bitmap font, read from the bytes of a PSF file.
+#[derive(Debug, Clone, Copy)]
+pub struct Font<'a> {
+    glyphs:          &'a [u8],
+    len:             usize,
+    width:           usize,
+    height:          usize,
+    bytes_per_glyph: usize,
+}
+
+impl<'a> Font<'a> {
+    pub fn parse(data: &'a [u8]) -> Result<Self, FontError> {
+        if data.starts_with(&PSF2_MAGIC) {
+            Self::parse_psf2(data)
+        }
+        else if data.starts_with(&PSF1_MAGIC) {
+            Self::parse_psf1(data)
+        }
+        else {
+            Err(FontError::BadMagic)
+        }
+    }
+
+    fn parse_psf1(data: &'a [u8]) -> Result<Self, FontError> {
+        let header =
+            data.get(..PSF1_HEADER_SIZE).ok_or(FontError::Truncated)?;
+        let len = if header[2] & PSF1_MODE_512 != 0 { 512 } else { 256 };
+        let height = header[3] as usize;
+        Self::new(&data[PSF1_HEADER_SIZE..], len, 8, height, height)
+    }
+
+    fn parse_psf2(data: &'a [u8]) -> Result<Self, FontError> {
+        let header =
+            data.get(..PSF2_HEADER_SIZE).ok_or(FontError::Truncated)?;
+        let field = |index: usize| {
+            let bytes = &header[index * 4..index * 4 + 4];
+            u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+                as usize
+        };
+
+        let header_size = field(2);
+        let glyphs = data.get(header_size..).ok_or(FontError::Truncated)?;
+        Self::new(glyphs, field(4), field(7), field(6), field(5))
+    }
+
+    fn new(
+        glyphs: &'a [u8], len: usize, width: usize, height: usize,
+        bytes_per_glyph: usize,
+    ) -> Result<Self, FontError> {
+        let bytes_per_row = (width + 7) / 8;
+        if width == 0 || height == 0 || bytes_per_glyph < bytes_per_row * height
+        {
+            return Err(FontError::BadHeader);
+        }
+
+        let size =
+            len.checked_mul(bytes_per_glyph).ok_or(FontError::BadHeader)?;
+        let glyphs = glyphs.get(..size).ok_or(FontError::Truncated)?;
+        Ok(Font { glyphs, len, width, height, bytes_per_glyph })
+    }
+
+    /// Returns the number of glyphs in the font.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the width of every glyph, in pixels.
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Returns the height of every glyph, in pixels.
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Returns the glyph at the given index, if the font has one.
+    pub fn glyph(&self, index: usize) -> Option<Glyph<'a>> {
+        let start = index.checked_mul(self.bytes_per_glyph)?;
+        let data = self.glyphs.get(start..start + self.bytes_per_glyph)?;
+        Some(Glyph { data, width: self.width, height: self.height })
+    }
+}
+
+/// The bitmap of a single character.
+#[derive(Debug, Clone, Copy)]
+pub struct Glyph<'a> {
+    /// Each row is padded to a whole number of bytes, with the leftmost pixel
+    /// in the top bit of its first byte.
+    data:   &'a [u8],
+    width:  usize,
+    height: usize,
+}
+
+impl Glyph<'_> {
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Returns true if the given pixel is part of the character, rather than
+    /// the background. Pixels outside of the glyph are background.
+    pub fn is_set(&self, x: usize, y: usize) -> bool {
+        if x >= self.width || y >= self.height {
+            return false;
+        }
+        let bytes_per_row = (self.width + 7) / 8;
+        let byte = self.data[y * bytes_per_row + x / 8];
+        byte & (0x80 >> (x % 8)) != 0
+    }
+}
+
+
+#[test_case]
+fn test_parse() {
+    let font = Font::parse(super::FONT).expect("failed to parse font");
+    assert_eq!((font.len(), font.width(), font.height()), (256, 8, 16));
+
+    let a = font.glyph(b'A' as usize).unwrap();
+    assert!(!a.is_set(3, 2) && a.is_set(3, 3) && a.is_set(4, 3));
+    assert!(font.glyph(256).is_none());
+
+    // a version 1 font with glyphs 2 rows high, only the first of which is
+    // there to begin with
+    let psf1 = [0x36, 0x04, 0x00, 0x02, 0xff, 0x81];
+    assert_eq!(Font::parse(&psf1).err(), Some(FontError::Truncated));
+    let mut data = alloc::vec::Vec::from(&psf1[..]);
+    data.resize(4 + 256 * 2, 0);
+    let font = Font::parse(&data).unwrap();
+    let glyph = font.glyph(0).unwrap();
+    assert!(glyph.is_set(0, 0) && glyph.is_set(7, 1) && !glyph.is_set(1, 1));
+
+    assert_eq!(Font::parse(b"font").err(), Some(FontError::BadMagic));
+}
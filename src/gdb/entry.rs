@@ -0,0 +1,122 @@
+//! The entry points of the breakpoint and debug exceptions. They're written in
+//! assembly so that they can save every general-purpose register where the
+//! stub can read and change them, which an `x86-interrupt` handler can't.
+
+use x86_64::VirtAddr;
+
+use crate::println;
+
+/// The breakpoint exception's vector. Each entry point pushes its vector, so
+/// that `gdb_trap` knows which exception it's handling.
+const BREAKPOINT: u64 = 3;
+
+/// The registers of the code that was interrupted, as saved on the stack by
+/// the CPU and the entry points, lowest address first. The entry points put
+/// them back from here, along with any changes, before returning to it.
+#[repr(C)]
+#[derive(Debug, Default, Clone)]
+pub(super) struct TrapFrame {
+    pub(super) rax:    u64,
+    pub(super) rbx:    u64,
+    pub(super) rcx:    u64,
+    pub(super) rdx:    u64,
+    pub(super) rsi:    u64,
+    pub(super) rdi:    u64,
+    pub(super) rbp:    u64,
+    pub(super) r8:     u64,
+    pub(super) r9:     u64,
+    pub(super) r10:    u64,
+    pub(super) r11:    u64,
+    pub(super) r12:    u64,
+    pub(super) r13:    u64,
+    pub(super) r14:    u64,
+    pub(super) r15:    u64,
+    pub(super) vector: u64,
+    // the rest is the interrupt stack frame that the CPU pushed
+    pub(super) rip:    u64,
+    pub(super) cs:     u64,
+    pub(super) rflags: u64,
+    pub(super) rsp:    u64,
+    pub(super) ss:     u64,
+}
+
+// The CPU aligns the stack to 16 bytes before pushing its five-word frame, so
+// after the vector and the fifteen registers it's eight bytes off, which has
+// to be fixed before calling into Rust. The target has no SSE, so there are no
+// other registers that Rust code could clobber.
+global_asm!(
+    ".global gdb_debug_entry",
+    "gdb_debug_entry:",
+    "push 1",
+    "jmp gdb_common_entry",
+    ".global gdb_breakpoint_entry",
+    "gdb_breakpoint_entry:",
+    "push 3",
+    "gdb_common_entry:",
+    "push r15",
+    "push r14",
+    "push r13",
+    "push r12",
+    "push r11",
+    "push r10",
+    "push r9",
+    "push r8",
+    "push rbp",
+    "push rdi",
+    "push rsi",
+    "push rdx",
+    "push rcx",
+    "push rbx",
+    "push rax",
+    "mov rdi, rsp",
+    "sub rsp, 8",
+    "cld",
+    "call gdb_trap",
+    "add rsp, 8",
+    "pop rax",
+    "pop rbx",
+    "pop rcx",
+    "pop rdx",
+    "pop rsi",
+    "pop rdi",
+    "pop rbp",
+    "pop r8",
+    "pop r9",
+    "pop r10",
+    "pop r11",
+    "pop r12",
+    "pop r13",
+    "pop r14",
+    "pop r15",
+    // the vector
+    "add rsp, 8",
+    "iretq",
+);
+
+extern "C" {
+    fn gdb_debug_entry();
+    fn gdb_breakpoint_entry();
+}
+
+/// Returns the address of the debug exception's entry point, for the IDT.
+pub(crate) fn debug_entry() -> VirtAddr {
+    VirtAddr::new(gdb_debug_entry as usize as u64)
+}
+
+/// Returns the address of the breakpoint exception's entry point, for the IDT.
+pub(crate) fn breakpoint_entry() -> VirtAddr {
+    VirtAddr::new(gdb_breakpoint_entry as usize as u64)
+}
+
+/// Called by both entry points, with interrupts disabled. Exceptions that the
+/// stub has nothing to do with are reported, as any other handler would.
+#[no_mangle]
+extern "C" fn gdb_trap(frame: &mut TrapFrame) {
+    let (handled, name) = match frame.vector {
+        BREAKPOINT => (super::on_breakpoint(frame), "BREAKPOINT"),
+        _ => (super::on_debug(frame), "DEBUG"),
+    };
+    if !handled {
+        println!("EXCEPTION: {}\n{:#?}", name, frame);
+    }
+}
@@ -0,0 +1,673 @@
+//! A stub for GDB's remote serial protocol, so that the kernel can be debugged
+//! from GDB on the host, over the serial port with the debugger role.
+//!
+//! While a port has that role, hitting a breakpoint stops the kernel until GDB
+//! resumes it; the shell's `debug` command does that on purpose. GDB can read
+//! and write memory, set breakpoints, single-step and continue. Under QEMU,
+//! give the debugger role to COM2, run with `-serial tcp::1234,server,nowait`
+//! after the first `-serial`, and connect with `target remote :1234`. GDB's
+//! Ctrl+C stops the kernel at the end of the debugger port's interrupt
+//! handler.
+//!
+//! The exceptions' entry points save every general-purpose register, along
+//! with the interrupt stack frame, so GDB can see and change all of them
+//! except CS and SS. The other segment registers aren't saved, so GDB is told
+//! that they're unavailable.
+//!
+//! Everything happens with interrupts disabled, polling the port, and without
+//! using the heap, since the kernel could have stopped anywhere.
+
+mod entry;
+mod packet;
+
+use core::fmt::Write;
+use core::ptr;
+
+use x86_64::registers::control::{Cr0, Cr0Flags};
+use x86_64::VirtAddr;
+
+use self::entry::TrapFrame;
+pub(crate) use self::entry::{breakpoint_entry, debug_entry};
+use self::packet::{parse_hex, split, Reply, PACKET_SIZE};
+use crate::memory;
+use crate::serial::{self, Role, SerialPort};
+use crate::sync::IrqSafeMutex;
+
+/// The most breakpoints that can be set at once.
+const MAX_BREAKPOINTS: usize = 32;
+/// The `int3` instruction, which breakpoints are made of.
+const INT3: u8 = 0xcc;
+/// The bit in RFLAGS that raises a debug exception after each instruction.
+const TRAP_FLAG: u64 = 1 << 8;
+/// Tells GDB that the kernel stopped with SIGTRAP, which it always does.
+const STOP_REPLY: &[u8] = b"S05";
+
+// GDB's numbers for the registers with special jobs
+const RSP: usize = 7;
+const RIP: usize = 16;
+const EFLAGS: usize = 17;
+const CS: usize = 18;
+const SS: usize = 19;
+/// The number of registers sent for `g`: the 16 general-purpose registers,
+/// RIP, EFLAGS and the six segment registers. GDB takes the ones after them,
+/// such as the FPU's, to be unavailable.
+const REGISTER_COUNT: usize = 24;
+
+static STUB: IrqSafeMutex<Stub> = IrqSafeMutex::new(Stub::new());
+
+/// Stops the kernel for GDB, as if it had hit a breakpoint where this is
+/// called. It's how GDB's Ctrl+C is handled.
+pub(crate) fn interrupt() {
+    x86_64::instructions::interrupts::int3();
+}
+
+/// Called when a breakpoint exception is raised. Returns false if the stub
+/// had nothing to do with the breakpoint, so it should be reported instead.
+fn on_breakpoint(frame: &mut TrapFrame) -> bool {
+    let mut stub = STUB.lock();
+    // RIP is after the `int3`
+    let addr = frame.rip - 1;
+    let hit = stub.breakpoints.is_inserted(addr);
+    let port = serial::role_port(Role::Debugger);
+    if port.is_none() && !hit {
+        return false;
+    }
+
+    // if it's one of GDB's, the instruction that it replaced has to be run
+    // instead
+    stub.breakpoints.restore();
+    if hit {
+        frame.rip = addr;
+    }
+
+    match port {
+        Some(port) => stub.run(&mut port.lock(), frame),
+        // the debugger role was taken away, so GDB's breakpoints stay out
+        None => stub.resumed = None,
+    }
+    true
+}
+
+/// Called when a debug exception is raised, which happens after each
+/// instruction while the trap flag is set. Returns false if the stub didn't
+/// set it.
+fn on_debug(frame: &mut TrapFrame) -> bool {
+    let mut stub = STUB.lock();
+    match stub.resumed {
+        Some(Resume::StepOver) => {
+            stub.breakpoints.insert();
+            set_trap_flag(frame, false);
+            stub.resumed = Some(Resume::Continue);
+        },
+        Some(Resume::Step) => {
+            set_trap_flag(frame, false);
+            match serial::role_port(Role::Debugger) {
+                Some(port) => stub.run(&mut port.lock(), frame),
+                None => stub.resumed = None,
+            }
+        },
+        _ => return false,
+    }
+    true
+}
+
+/// How GDB last resumed the kernel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Resume {
+    Continue,
+    /// Running the instruction under a breakpoint, with the breakpoints out of
+    /// memory, before continuing with them put back.
+    StepOver,
+    Step,
+}
+
+/// What to do after a command from GDB.
+#[derive(Debug, PartialEq, Eq)]
+enum Action {
+    Reply,
+    Resume(Resume),
+    /// Sends the reply, if there is one, and stops talking to GDB.
+    Detach,
+}
+
+/// Why a command failed, which GDB is told as an errno value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Error {
+    NotPermitted = 1,
+    BadAddress   = 14,
+    Invalid      = 22,
+    NoSpace      = 28,
+}
+
+struct Stub {
+    breakpoints: Breakpoints,
+    /// How GDB last resumed the kernel, or None if it hasn't since it
+    /// attached, so it isn't waiting to hear that the kernel has stopped.
+    resumed:     Option<Resume>,
+    packet:      [u8; PACKET_SIZE],
+    reply:       [u8; PACKET_SIZE],
+}
+
+impl Stub {
+    const fn new() -> Self {
+        Stub {
+            breakpoints: Breakpoints::new(),
+            resumed:     None,
+            packet:      [0; PACKET_SIZE],
+            reply:       [0; PACKET_SIZE],
+        }
+    }
+
+    /// Handles commands from GDB until it resumes the kernel. The breakpoints
+    /// should have been taken out of memory.
+    fn run(&mut self, port: &mut SerialPort, frame: &mut TrapFrame) {
+        if self.resumed.is_some() {
+            packet::write(port, STOP_REPLY);
+        }
+
+        loop {
+            let len = packet::read(port, &mut self.packet);
+            let mut reply = Reply::new(&mut self.reply);
+            let action = handle(
+                &self.packet[..len],
+                &mut reply,
+                &mut self.breakpoints,
+                frame,
+            );
+
+            let resume = match action {
+                Ok(Action::Reply) => {
+                    packet::write(port, reply.as_bytes());
+                    continue;
+                },
+                Ok(Action::Resume(resume)) => resume,
+                Ok(Action::Detach) => {
+                    if !reply.as_bytes().is_empty() {
+                        packet::write(port, reply.as_bytes());
+                    }
+                    self.resumed = None;
+                    self.breakpoints.clear();
+                    set_trap_flag(frame, false);
+                    return;
+                },
+                Err(err) => {
+                    let mut buffer = [0; 3];
+                    let mut reply = Reply::new(&mut buffer);
+                    let _ = write!(reply, "E{:02x}", err as u8);
+                    packet::write(port, reply.as_bytes());
+                    continue;
+                },
+            };
+
+            let resume = match resume {
+                Resume::Continue if self.breakpoints.contains(frame.rip) =>
+                    Resume::StepOver,
+                Resume::Continue => {
+                    self.breakpoints.insert();
+                    Resume::Continue
+                },
+                resume => resume,
+            };
+            set_trap_flag(frame, resume != Resume::Continue);
+            self.resumed = Some(resume);
+            return;
+        }
+    }
+}
+
+/// Carries out a command from GDB, adding anything to be sent back to the
+/// reply. Unknown commands get an empty reply, which tells GDB that they
+/// aren't supported.
+fn handle(
+    packet: &[u8], reply: &mut Reply, breakpoints: &mut Breakpoints,
+    frame: &mut TrapFrame,
+) -> Result<Action, Error> {
+    let (&command, args) = match packet.split_first() {
+        Some(split) => split,
+        None => return Ok(Action::Reply),
+    };
+
+    match command {
+        b'?' => {
+            reply.push(STOP_REPLY);
+        },
+        b'g' =>
+            for n in 0..REGISTER_COUNT {
+                push_register(reply, frame, n);
+            },
+        b'G' => {
+            let mut values = args;
+            for n in 0..REGISTER_COUNT {
+                let digits = values.get(..register_size(n) * 2);
+                let digits = match digits {
+                    Some(digits) => digits,
+                    None => break,
+                };
+                values = &values[digits.len()..];
+                // only the registers that can be changed are, and GDB sends
+                // back the ones that can't as they were, or as unavailable
+                if let Some(value) = parse_le_hex(digits) {
+                    let _ = write_register(frame, n, value);
+                }
+            }
+            reply.push(b"OK");
+        },
+        b'p' => {
+            let n = parse_hex(args).ok_or(Error::Invalid)? as usize;
+            if n >= REGISTER_COUNT {
+                return Err(Error::Invalid);
+            }
+            push_register(reply, frame, n);
+        },
+        b'P' => {
+            let (n, value) = split(args, b'=').ok_or(Error::Invalid)?;
+            let n = parse_hex(n).ok_or(Error::Invalid)? as usize;
+            let value = parse_le_hex(value).ok_or(Error::Invalid)?;
+            write_register(frame, n, value)?;
+            reply.push(b"OK");
+        },
+        b'm' => {
+            let (addr, len) = parse_range(args)?;
+            // each byte takes two hex digits
+            let len = len.min((PACKET_SIZE / 2) as u64);
+            if !is_mapped(addr, len) {
+                return Err(Error::BadAddress);
+            }
+            for addr in addr..addr + len {
+                reply.push_hex(&[unsafe { peek(addr) }]);
+            }
+        },
+        b'M' => {
+            let (range, data) = split(args, b':').ok_or(Error::Invalid)?;
+            let (addr, len) = parse_range(range)?;
+            if data.len() as u64 != len * 2 {
+                return Err(Error::Invalid);
+            }
+            if !is_mapped(addr, len) {
+                return Err(Error::BadAddress);
+            }
+            for (addr, digits) in (addr..).zip(data.chunks(2)) {
+                let byte = parse_hex(digits).ok_or(Error::Invalid)?;
+                unsafe { poke(addr, byte as u8) };
+            }
+            reply.push(b"OK");
+        },
+        b'Z' | b'z' => {
+            let (kind, args) = split(args, b',').ok_or(Error::Invalid)?;
+            // only software breakpoints are supported
+            if kind != b"0" {
+                return Ok(Action::Reply);
+            }
+            let (addr, _) = split(args, b',').ok_or(Error::Invalid)?;
+            let addr = parse_hex(addr).ok_or(Error::Invalid)?;
+
+            if command == b'Z' {
+                if !is_mapped(addr, 1) {
+                    return Err(Error::BadAddress);
+                }
+                if !breakpoints.add(addr) {
+                    return Err(Error::NoSpace);
+                }
+            }
+            else {
+                breakpoints.remove(addr);
+            }
+            reply.push(b"OK");
+        },
+        b'c' | b's' => {
+            if !args.is_empty() {
+                let addr = parse_hex(args).ok_or(Error::Invalid)?;
+                write_register(frame, RIP, addr)?;
+            }
+            return Ok(Action::Resume(match command {
+                b'c' => Resume::Continue,
+                _ => Resume::Step,
+            }));
+        },
+        b'D' => {
+            reply.push(b"OK");
+            return Ok(Action::Detach);
+        },
+        // GDB doesn't wait for a reply
+        b'k' => return Ok(Action::Detach),
+        b'H' => {
+            reply.push(b"OK");
+        },
+        b'q' =>
+            if args.starts_with(b"Supported") {
+                let _ = write!(reply, "PacketSize={:x}", PACKET_SIZE);
+            }
+            else if args.starts_with(b"Attached") {
+                reply.push(b"1");
+            },
+        _ => {},
+    }
+    Ok(Action::Reply)
+}
+
+/// GDB's software breakpoints. They're only in memory while the kernel runs,
+/// so that GDB sees the code as it was while the kernel is stopped.
+struct Breakpoints {
+    slots: [Option<Breakpoint>; MAX_BREAKPOINTS],
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Breakpoint {
+    addr:     u64,
+    /// The byte that the `int3` replaced, while it's in memory.
+    original: Option<u8>,
+}
+
+impl Breakpoints {
+    const fn new() -> Self {
+        Breakpoints { slots: [None; MAX_BREAKPOINTS] }
+    }
+
+    fn contains(&self, addr: u64) -> bool {
+        self.slots.iter().flatten().any(|breakpoint| breakpoint.addr == addr)
+    }
+
+    /// Returns true if there's a breakpoint at the address that's in memory.
+    fn is_inserted(&self, addr: u64) -> bool {
+        self.slots.iter().flatten().any(|breakpoint| {
+            breakpoint.addr == addr && breakpoint.original.is_some()
+        })
+    }
+
+    /// Sets a breakpoint, returning false if there's no room for it.
+    fn add(&mut self, addr: u64) -> bool {
+        if self.contains(addr) {
+            return true;
+        }
+        match self.slots.iter_mut().find(|slot| slot.is_none()) {
+            Some(slot) => {
+                *slot = Some(Breakpoint { addr, original: None });
+                true
+            },
+            None => false,
+        }
+    }
+
+    fn remove(&mut self, addr: u64) {
+        for slot in self.slots.iter_mut() {
+            if slot.map_or(false, |breakpoint| breakpoint.addr == addr) {
+                *slot = None;
+            }
+        }
+    }
+
+    fn clear(&mut self) {
+        self.slots = [None; MAX_BREAKPOINTS];
+    }
+
+    /// Puts the breakpoints into memory, skipping any whose pages have been
+    /// unmapped since they were set.
+    fn insert(&mut self) {
+        for breakpoint in self.slots.iter_mut().flatten() {
+            if breakpoint.original.is_none() && is_mapped(breakpoint.addr, 1) {
+                unsafe {
+                    breakpoint.original = Some(peek(breakpoint.addr));
+                    poke(breakpoint.addr, INT3);
+                }
+            }
+        }
+    }
+
+    /// Takes the breakpoints out of memory.
+    fn restore(&mut self) {
+        for breakpoint in self.slots.iter_mut().flatten() {
+            if let Some(original) = breakpoint.original.take() {
+                unsafe { poke(breakpoint.addr, original) };
+            }
+        }
+    }
+}
+
+/// Parses the `addr,len` that GDB gives for memory commands.
+fn parse_range(args: &[u8]) -> Result<(u64, u64), Error> {
+    let (addr, len) = split(args, b',').ok_or(Error::Invalid)?;
+    let addr = parse_hex(addr).ok_or(Error::Invalid)?;
+    let len = parse_hex(len).ok_or(Error::Invalid)?;
+    match addr.checked_add(len) {
+        Some(_) => Ok((addr, len)),
+        None => Err(Error::BadAddress),
+    }
+}
+
+/// Parses a register's value, which GDB sends as its bytes in memory order,
+/// so least significant first.
+fn parse_le_hex(digits: &[u8]) -> Option<u64> {
+    if digits.is_empty() || digits.len() % 2 != 0 || digits.len() > 16 {
+        return None;
+    }
+    digits
+        .chunks(2)
+        .rev()
+        .try_fold(0, |value, byte| Some(value << 8 | parse_hex(byte)?))
+}
+
+/// Returns true if every byte in the range is in a mapped page.
+fn is_mapped(addr: u64, len: u64) -> bool {
+    let last = match len.checked_sub(1) {
+        Some(offset) => addr.saturating_add(offset),
+        None => return true,
+    };
+
+    let mut page = addr & !0xfff;
+    loop {
+        match VirtAddr::try_new(page) {
+            Ok(page) if memory::translate(page).is_some() => {},
+            _ => return false,
+        }
+        if page >= last & !0xfff {
+            return true;
+        }
+        page += 0x1000;
+    }
+}
+
+/// Reads a byte, which has to be mapped.
+unsafe fn peek(addr: u64) -> u8 {
+    ptr::read_volatile(addr as *const u8)
+}
+
+/// Writes a byte, which has to be mapped, even if its page is read-only, as
+/// the kernel's code is.
+unsafe fn poke(addr: u64, byte: u8) {
+    let flags = Cr0::read();
+    Cr0::write(flags - Cr0Flags::WRITE_PROTECT);
+    ptr::write_volatile(addr as *mut u8, byte);
+    Cr0::write(flags);
+}
+
+/// Returns the size of the register in bytes, as GDB expects it.
+fn register_size(n: usize) -> usize {
+    if n <= RIP {
+        8
+    }
+    else {
+        4
+    }
+}
+
+/// Adds the register's value to the reply, as its bytes in memory order, or
+/// `x`s if it's unavailable.
+fn push_register(reply: &mut Reply, frame: &TrapFrame, n: usize) {
+    let value = match n {
+        CS => Some(frame.cs),
+        SS => Some(frame.ss),
+        _ => register(frame, n),
+    };
+
+    let size = register_size(n);
+    match value {
+        Some(value) => reply.push_hex(&value.to_le_bytes()[..size]),
+        None => (0..size).all(|_| reply.push(b"xx")),
+    };
+}
+
+/// Returns the value of a general-purpose register, RIP or RFLAGS.
+fn register(frame: &TrapFrame, n: usize) -> Option<u64> {
+    let mut frame = frame.clone();
+    register_mut(&mut frame, n).copied()
+}
+
+/// Returns a general-purpose register, RIP or RFLAGS, which are the registers
+/// that can be changed.
+fn register_mut(frame: &mut TrapFrame, n: usize) -> Option<&mut u64> {
+    let register = match n {
+        0 => &mut frame.rax,
+        1 => &mut frame.rbx,
+        2 => &mut frame.rcx,
+        3 => &mut frame.rdx,
+        4 => &mut frame.rsi,
+        5 => &mut frame.rdi,
+        6 => &mut frame.rbp,
+        RSP => &mut frame.rsp,
+        8 => &mut frame.r8,
+        9 => &mut frame.r9,
+        10 => &mut frame.r10,
+        11 => &mut frame.r11,
+        12 => &mut frame.r12,
+        13 => &mut frame.r13,
+        14 => &mut frame.r14,
+        15 => &mut frame.r15,
+        RIP => &mut frame.rip,
+        EFLAGS => &mut frame.rflags,
+        _ => return None,
+    };
+    Some(register)
+}
+
+/// Changes a general-purpose register, RIP or RFLAGS. Any other register
+/// can't be changed, and RSP and RIP can't be given an address that isn't
+/// canonical.
+fn write_register(
+    frame: &mut TrapFrame, n: usize, value: u64,
+) -> Result<(), Error> {
+    let register = register_mut(frame, n).ok_or(Error::NotPermitted)?;
+    // GDB is trusted to know what it's doing to the kernel otherwise
+    if (n == RSP || n == RIP) && VirtAddr::try_new(value).is_err() {
+        return Err(Error::Invalid);
+    }
+    *register = value;
+    Ok(())
+}
+
+fn set_trap_flag(frame: &mut TrapFrame, set: bool) {
+    if set {
+        frame.rflags |= TRAP_FLAG;
+    }
+    else {
+        frame.rflags &= !TRAP_FLAG;
+    }
+}
+
+#[test_case]
+fn test_registers() {
+    assert_eq!(parse_le_hex(b"efbeadde"), Some(0xdead_beef));
+    assert_eq!(parse_le_hex(b"xxxxxxxx"), None);
+    assert_eq!(parse_le_hex(b"123"), None);
+    assert_eq!(parse_range(b"1000,10").ok(), Some((0x1000, 0x10)));
+    assert!(parse_range(b"ffffffffffffffff,2").is_err());
+
+    let mut buffer = [0; 16];
+    let mut reply = Reply::new(&mut buffer);
+    assert!(reply.push_hex(&0x0123_u64.to_le_bytes()[..register_size(EFLAGS)]));
+    assert_eq!(reply.as_bytes(), b"23010000");
+}
+
+#[test_case]
+fn test_handle() {
+    use alloc::boxed::Box;
+    use alloc::string::String;
+    use alloc::{format, vec};
+
+    fn run(
+        packet: &str, breakpoints: &mut Breakpoints, frame: &mut TrapFrame,
+    ) -> Result<String, Error> {
+        let mut buffer = vec![0; PACKET_SIZE];
+        let mut reply = Reply::new(&mut buffer);
+        assert_eq!(
+            handle(packet.as_bytes(), &mut reply, breakpoints, frame)?,
+            Action::Reply
+        );
+        Ok(String::from_utf8(reply.as_bytes().into()).unwrap())
+    }
+
+    let mut breakpoints = Breakpoints::new();
+    let mut frame = TrapFrame {
+        rax: 0x0123_4567_89ab_cdef,
+        r15: 0xf,
+        rip: 0xffff_8000_0000_1000,
+        cs: 0x8,
+        rflags: 0x202,
+        rsp: 0x4000,
+        ss: 0x10,
+        ..TrapFrame::default()
+    };
+
+    let registers = run("g", &mut breakpoints, &mut frame).unwrap();
+    assert_eq!(registers.len(), (17 * 8 + 7 * 4) * 2);
+    assert_eq!(&registers[..16], "efcdab8967452301");
+    assert_eq!(&registers[RSP * 16..][..16], "0040000000000000");
+    assert_eq!(&registers[15 * 16..][..16], "0f00000000000000");
+    assert_eq!(&registers[RIP * 16..][..16], "001000000080ffff");
+    assert_eq!(&registers[272..296], "020200000800000010000000");
+    assert!(registers[296..].bytes().all(|digit| digit == b'x'));
+
+    assert_eq!(
+        run("P3=efbeadde00000000", &mut breakpoints, &mut frame),
+        Ok("OK".into())
+    );
+    assert_eq!(frame.rdx, 0xdead_beef);
+    assert_eq!(
+        run("p3", &mut breakpoints, &mut frame).unwrap(),
+        "efbeadde00000000"
+    );
+    assert_eq!(
+        run("P12=10000000", &mut breakpoints, &mut frame),
+        Err(Error::NotPermitted)
+    );
+    // a non-canonical RIP is refused the same way however it's given
+    assert_eq!(
+        run("P10=0000000000800000", &mut breakpoints, &mut frame),
+        Err(Error::Invalid)
+    );
+    assert_eq!(
+        run("c800000000000", &mut breakpoints, &mut frame),
+        Err(Error::Invalid)
+    );
+
+    // code that's never run, so that breakpoints can be put in it
+    let code = Box::leak(Box::new([0x90u8; PACKET_SIZE]));
+    let addr = code.as_mut_ptr() as u64;
+
+    let set = format!("Z0,{:x},1", addr);
+    assert_eq!(run(&set, &mut breakpoints, &mut frame).unwrap(), "OK");
+    assert!(breakpoints.contains(addr) && !breakpoints.is_inserted(addr));
+    breakpoints.insert();
+    assert!(breakpoints.is_inserted(addr));
+    assert_eq!(unsafe { peek(addr) }, INT3);
+    breakpoints.restore();
+    assert!(!breakpoints.is_inserted(addr));
+    assert_eq!(unsafe { peek(addr) }, 0x90);
+    let clear = format!("z0,{:x},1", addr);
+    assert_eq!(run(&clear, &mut breakpoints, &mut frame).unwrap(), "OK");
+    assert!(!breakpoints.contains(addr));
+
+    let write = format!("M{:x},2:abcd", addr);
+    assert_eq!(run(&write, &mut breakpoints, &mut frame).unwrap(), "OK");
+    let read = format!("m{:x},4", addr);
+    assert_eq!(run(&read, &mut breakpoints, &mut frame).unwrap(), "abcd9090");
+    let short = format!("M{:x},2:ab", addr);
+    assert_eq!(run(&short, &mut breakpoints, &mut frame), Err(Error::Invalid));
+    // a byte takes two hex digits, so only half a packet's worth can be read
+    let long = format!("m{:x},10000", addr);
+    let bytes = run(&long, &mut breakpoints, &mut frame).unwrap();
+    assert_eq!(bytes.len(), PACKET_SIZE);
+    assert_eq!(
+        run("mffffffffffffffff,2", &mut breakpoints, &mut frame),
+        Err(Error::BadAddress)
+    );
+}
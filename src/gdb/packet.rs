@@ -0,0 +1,170 @@
+//! The framing of GDB's packets, which are sent as `$data#cc`, where `cc` is
+//! the sum of the data's bytes modulo 256, in hex. The other side answers each
+//! packet with `+`, or with `-` to have it sent again.
+
+use core::fmt;
+
+use crate::serial::SerialPort;
+
+/// The most data a packet can hold, which is what GDB is told in reply to
+/// `qSupported`.
+pub(super) const PACKET_SIZE: usize = 4096;
+
+/// Waits for a packet with the right checksum, acknowledges it, and copies
+/// its data into the buffer, returning how long it is.
+///
+/// Anything between packets, such as GDB's acknowledgements and its Ctrl+C,
+/// is skipped.
+pub(super) fn read(port: &mut SerialPort, buffer: &mut [u8]) -> usize {
+    loop {
+        while receive(port) != b'$' {}
+
+        let mut len = 0;
+        let mut sum = 0u8;
+        let mut overflowed = false;
+        loop {
+            let byte = receive(port);
+            if byte == b'#' {
+                break;
+            }
+            match buffer.get_mut(len) {
+                Some(slot) => *slot = byte,
+                None => overflowed = true,
+            }
+            len += 1;
+            sum = sum.wrapping_add(byte);
+        }
+
+        let checksum = [receive(port), receive(port)];
+        if !overflowed && parse_hex(&checksum) == Some(u64::from(sum)) {
+            port.send(b"+");
+            return len;
+        }
+        port.send(b"-");
+    }
+}
+
+/// Sends a packet, sending it again until GDB acknowledges it.
+pub(super) fn write(port: &mut SerialPort, data: &[u8]) {
+    let sum = data.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte));
+    let checksum =
+        [HEX_DIGITS[usize::from(sum >> 4)], HEX_DIGITS[usize::from(sum & 0xf)]];
+
+    loop {
+        port.send(b"$");
+        port.send(data);
+        port.send(b"#");
+        port.send(&checksum);
+
+        loop {
+            match receive(port) {
+                b'+' => return,
+                b'-' => break,
+                _ => {},
+            }
+        }
+    }
+}
+
+/// Waits for a byte to arrive.
+fn receive(port: &mut SerialPort) -> u8 {
+    loop {
+        if let Some(byte) = port.receive() {
+            return byte;
+        }
+        core::hint::spin_loop();
+    }
+}
+
+const HEX_DIGITS: &[u8; 16] = b"0123456789abcdef";
+
+/// Parses a hex number, as used for addresses and lengths. The number can't
+/// be empty, or too big for a u64.
+pub(super) fn parse_hex(digits: &[u8]) -> Option<u64> {
+    if digits.is_empty() || digits.len() > 16 {
+        return None;
+    }
+    digits.iter().try_fold(0, |value, &digit| {
+        let digit = char::from(digit).to_digit(16)?;
+        Some(value << 4 | u64::from(digit))
+    })
+}
+
+/// Splits the bytes at the first `separator`, returning the parts before and
+/// after it.
+pub(super) fn split(bytes: &[u8], separator: u8) -> Option<(&[u8], &[u8])> {
+    let at = bytes.iter().position(|&byte| byte == separator)?;
+    Some((&bytes[..at], &bytes[at + 1..]))
+}
+
+/// The data of a packet to be sent, built up in a fixed buffer, since the stub
+/// can't use the heap.
+pub(super) struct Reply<'a> {
+    buffer: &'a mut [u8],
+    len:    usize,
+}
+
+impl<'a> Reply<'a> {
+    pub(super) fn new(buffer: &'a mut [u8]) -> Self {
+        Reply { buffer, len: 0 }
+    }
+
+    /// Returns the data added so far.
+    pub(super) fn as_bytes(&self) -> &[u8] {
+        &self.buffer[..self.len]
+    }
+
+    /// Adds the bytes, returning false if there isn't room for them.
+    pub(super) fn push(&mut self, bytes: &[u8]) -> bool {
+        let end = self.len + bytes.len();
+        match self.buffer.get_mut(self.len..end) {
+            Some(slots) => {
+                slots.copy_from_slice(bytes);
+                self.len = end;
+                true
+            },
+            None => false,
+        }
+    }
+
+    /// Adds the bytes as pairs of hex digits.
+    pub(super) fn push_hex(&mut self, bytes: &[u8]) -> bool {
+        bytes.iter().all(|&byte| {
+            self.push(&[
+                HEX_DIGITS[usize::from(byte >> 4)],
+                HEX_DIGITS[usize::from(byte & 0xf)],
+            ])
+        })
+    }
+}
+
+impl fmt::Write for Reply<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        if self.push(s.as_bytes()) {
+            Ok(())
+        }
+        else {
+            Err(fmt::Error)
+        }
+    }
+}
+
+
+#[test_case]
+fn test_packet_helpers() {
+    assert_eq!(parse_hex(b"ffff8000deadbeef"), Some(0xffff_8000_dead_beef));
+    assert_eq!(parse_hex(b"1A"), Some(0x1a));
+    assert_eq!(parse_hex(b""), None);
+    assert_eq!(parse_hex(b"12g"), None);
+    assert_eq!(parse_hex(b"10000000000000000"), None);
+    assert_eq!(
+        split(b"1000,4:beef", b','),
+        Some((&b"1000"[..], &b"4:beef"[..]))
+    );
+
+    let mut buffer = [0; 8];
+    let mut reply = Reply::new(&mut buffer);
+    assert!(reply.push(b"S") && reply.push_hex(&[0x05, 0xab]));
+    assert_eq!(reply.as_bytes(), b"S05ab");
+    assert!(!reply.push_hex(&[0; 2]));
+}
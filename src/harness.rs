@@ -0,0 +1,99 @@
+//! Lets the host choose which tests to run, by sending commands over the
+//! console's serial port when the test kernel says it's ready for them.
+//!
+//! Commands are sent one per line:
+//!
+//! - `list` prints the name of every test.
+//! - `run [filter]` runs the tests with `filter` in their names, or every test
+//!   without one, then exits QEMU.
+//! - `mem` prints the heap's usage.
+//! - `exit` exits QEMU without running anything.
+//!
+//! If nothing is sent within `FIRST_COMMAND_TIMEOUT_MS`, every test is run, so
+//! the tests still run by themselves under `cargo test`. Once something has
+//! been sent, the harness waits for as long as it takes to get a `run`.
+
+use alloc::string::String;
+use core::task::{Context, Poll};
+
+use futures_util::stream::StreamExt;
+use futures_util::task::noop_waker;
+
+use crate::serial::SerialStream;
+use crate::{
+    allocator, exit_qemu, interrupts, serial_println, QemuExitCode, Test,
+};
+
+/// How long to wait for the host to send its first command.
+const FIRST_COMMAND_TIMEOUT_MS: u64 = 250;
+
+/// Returns true if commands can be read, which needs the kernel to have been
+/// initialized, for the heap and the serial port's interrupt. Interrupts are
+/// only enabled by `init`.
+pub(crate) fn is_available() -> bool {
+    x86_64::instructions::interrupts::are_enabled()
+}
+
+/// Handles commands from the host until it asks for tests to be run,
+/// returning the filter that it gave, if any.
+pub(crate) fn read_commands(tests: &[&dyn Test]) -> Option<String> {
+    let mut input = SerialStream::new();
+    serial_println!("ready for test commands");
+
+    let mut deadline = Some(interrupts::uptime_ms() + FIRST_COMMAND_TIMEOUT_MS);
+    while let Some(line) = read_line(&mut input, deadline) {
+        deadline = None;
+
+        let mut words = line.split_whitespace();
+        match (words.next(), words.next()) {
+            (Some("list"), None) =>
+                for test in tests {
+                    serial_println!("{}", test.name());
+                },
+            (Some("run"), filter) => return filter.map(String::from),
+            (Some("mem"), None) => {
+                let stats = allocator::stats();
+                serial_println!("heap:   {:>8} bytes", stats.size);
+                serial_println!("used:   {:>8} bytes", stats.used);
+                serial_println!("pooled: {:>8} bytes", stats.pooled);
+                serial_println!("free:   {:>8} bytes", stats.free);
+            },
+            (Some("exit"), None) => exit_qemu(QemuExitCode::Success),
+            _ => serial_println!("unknown command: {}", line),
+        }
+    }
+    None
+}
+
+/// Waits for a line that isn't blank, or until the deadline passes before any
+/// of one has arrived.
+fn read_line(
+    input: &mut SerialStream, deadline: Option<u64>,
+) -> Option<String> {
+    let waker = noop_waker();
+    let mut cx = Context::from_waker(&waker);
+    let mut line = String::new();
+
+    loop {
+        match input.poll_next_unpin(&mut cx) {
+            Poll::Ready(Some(b'\r')) | Poll::Ready(Some(b'\n')) => {
+                if !line.trim().is_empty() {
+                    return Some(line);
+                }
+                line.clear();
+            },
+            Poll::Ready(Some(byte)) => line.push(byte as char),
+            Poll::Ready(None) => return None,
+            Poll::Pending => {
+                let expired = deadline.map_or(false, |deadline| {
+                    interrupts::uptime_ms() >= deadline
+                });
+                if expired && line.is_empty() {
+                    return None;
+                }
+                // woken by the serial port's interrupt, or the next tick
+                x86_64::instructions::hlt();
+            },
+        }
+    }
+}
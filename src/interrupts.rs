@@ -1,21 +1,34 @@
+use core::sync::atomic::{AtomicU64, Ordering};
+
 use lazy_static::lazy_static;
 use pic8259::ChainedPics;
-use spin;
 use x86_64::structures::idt;
 
-use crate::{gdt, halt, println};
+use crate::sync::IrqSafeMutex;
+use crate::{gdb, gdt, halt, println};
 
 pub const PIC1_OFFSET: u8 = 32;
 pub const PIC2_OFFSET: u8 = PIC1_OFFSET + 8;
 
-pub static PICS: spin::Mutex<ChainedPics> =
-    spin::Mutex::new(unsafe { ChainedPics::new(PIC1_OFFSET, PIC2_OFFSET) });
+/// The rate at which the PIT counts down, in Hz.
+const PIT_FREQUENCY: u64 = 1_193_182;
+/// The PIT's reload value, which is left at its default of 65536.
+const PIT_DIVISOR: u64 = 65536;
+
+static TICKS: AtomicU64 = AtomicU64::new(0);
+
+pub static PICS: IrqSafeMutex<ChainedPics> =
+    IrqSafeMutex::new(unsafe { ChainedPics::new(PIC1_OFFSET, PIC2_OFFSET) });
 
 #[repr(u8)]
 #[derive(Debug, Clone, Copy)]
 pub enum InterruptIndex {
     Timer = PIC1_OFFSET,
     Keyboard,
+    Com2  = PIC1_OFFSET + 3,
+    Com1  = PIC1_OFFSET + 4,
+    /// Wired to the second PIC, with IRQ2 cascading to it.
+    Mouse = PIC2_OFFSET + 4,
 }
 
 impl InterruptIndex {
@@ -32,15 +45,24 @@ lazy_static! {
     static ref IDT: idt::InterruptDescriptorTable = {
         let mut idt = idt::InterruptDescriptorTable::new();
 
-        idt.breakpoint.set_handler_fn(breakpoint_handler);
         idt.page_fault.set_handler_fn(page_fault_handler);
 
         idt[InterruptIndex::Timer.as_usize()]
             .set_handler_fn(timer_interrupt_handler);
         idt[InterruptIndex::Keyboard.as_usize()]
             .set_handler_fn(keyboard_interrupt_handler);
+        idt[InterruptIndex::Com2.as_usize()]
+            .set_handler_fn(com2_interrupt_handler);
+        idt[InterruptIndex::Com1.as_usize()]
+            .set_handler_fn(com1_interrupt_handler);
+        idt[InterruptIndex::Mouse.as_usize()]
+            .set_handler_fn(mouse_interrupt_handler);
 
         unsafe {
+            // these save every register for the GDB stub, so they're written
+            // in assembly
+            idt.debug.set_handler_addr(gdb::debug_entry());
+            idt.breakpoint.set_handler_addr(gdb::breakpoint_entry());
             idt.double_fault
                 .set_handler_fn(double_fault_handler)
                 .set_stack_index(gdt::DOUBLE_FAULT_IST_INDEX);
@@ -54,6 +76,42 @@ pub fn init_idt() {
     IDT.load();
 }
 
+/// Unmasks the given interrupt line on the PICs, along with the cascade line
+/// if it's on the second PIC.
+///
+/// The firmware may have left lines masked, and `ChainedPics::initialize`
+/// keeps whatever masks it finds.
+pub fn unmask_irq(irq: u8) {
+    use x86_64::instructions::port::Port;
+
+    let _pics = PICS.lock();
+    let mut pic1_data: Port<u8> = Port::new(0x21);
+    let mut pic2_data: Port<u8> = Port::new(0xa1);
+
+    unsafe {
+        if irq < 8 {
+            let mask = pic1_data.read();
+            pic1_data.write(mask & !(1 << irq));
+        }
+        else {
+            let mask = pic2_data.read();
+            pic2_data.write(mask & !(1 << (irq - 8)));
+            let mask = pic1_data.read();
+            pic1_data.write(mask & !(1 << 2));
+        }
+    }
+}
+
+/// Returns the number of timer interrupts since boot.
+pub fn ticks() -> u64 {
+    TICKS.load(Ordering::Relaxed)
+}
+
+/// Returns the time since interrupts were enabled, in milliseconds.
+pub fn uptime_ms() -> u64 {
+    ticks() * PIT_DIVISOR * 1000 / PIT_FREQUENCY
+}
+
 extern "x86-interrupt" fn double_fault_handler(
     stack_frame: idt::InterruptStackFrame, _error_code: u64,
 ) -> ! {
@@ -75,6 +133,9 @@ extern "x86-interrupt" fn page_fault_handler(
 extern "x86-interrupt" fn timer_interrupt_handler(
     _stack_frame: idt::InterruptStackFrame,
 ) {
+    TICKS.fetch_add(1, Ordering::Relaxed);
+    crate::framebuffer::on_timer_tick();
+
     unsafe {
         PICS.lock().notify_end_of_interrupt(InterruptIndex::Timer.as_u8());
     }
@@ -94,10 +155,46 @@ extern "x86-interrupt" fn keyboard_interrupt_handler(
     }
 }
 
-extern "x86-interrupt" fn breakpoint_handler(
-    stack_frame: idt::InterruptStackFrame,
+extern "x86-interrupt" fn com2_interrupt_handler(
+    _stack_frame: idt::InterruptStackFrame,
+) {
+    let stop = super::serial::on_interrupt(3);
+
+    unsafe {
+        PICS.lock().notify_end_of_interrupt(InterruptIndex::Com2.as_u8());
+    }
+    // only once the PIC and the port are done with, as GDB polls the port
+    if stop {
+        gdb::interrupt();
+    }
+}
+
+extern "x86-interrupt" fn com1_interrupt_handler(
+    _stack_frame: idt::InterruptStackFrame,
+) {
+    let stop = super::serial::on_interrupt(4);
+
+    unsafe {
+        PICS.lock().notify_end_of_interrupt(InterruptIndex::Com1.as_u8());
+    }
+    // only once the PIC and the port are done with, as GDB polls the port
+    if stop {
+        gdb::interrupt();
+    }
+}
+
+extern "x86-interrupt" fn mouse_interrupt_handler(
+    _stack_frame: idt::InterruptStackFrame,
 ) {
-    println!("EXCEPTION: BREAKPOINT\n{:#?}", stack_frame);
+    use x86_64::instructions::port::Port;
+
+    let mut port = Port::new(0x60);
+    let byte: u8 = unsafe { port.read() };
+    super::task::mouse::push_byte(byte);
+
+    unsafe {
+        PICS.lock().notify_end_of_interrupt(InterruptIndex::Mouse.as_u8());
+    }
 }
 
 
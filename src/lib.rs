@@ -4,16 +4,25 @@
 #![feature(custom_test_frameworks)]
 #![feature(alloc_error_handler)]
 #![feature(abi_x86_interrupt)]
+#![feature(global_asm)]
 #![test_runner(crate::test_runner)]
 #![reexport_test_harness_main = "run_test"]
 
 extern crate alloc;
 
 pub mod allocator;
+pub mod console;
+pub mod framebuffer;
+pub mod gdb;
 pub mod gdt;
+mod harness;
 pub mod interrupts;
+pub mod logging;
 pub mod memory;
+pub mod ps2;
 pub mod serial;
+pub mod shell;
+pub mod sync;
 pub mod task;
 pub mod vga;
 
@@ -26,6 +35,7 @@ pub fn init(
 ) -> (OffsetPageTable, memory::BootInfoFrameAllocator) {
     use x86_64::VirtAddr;
 
+    logging::init().expect("logger already set");
     gdt::init();
 
     // Load the Interrupt Descriptor Table.
@@ -33,6 +43,13 @@ pub fn init(
 
     // Enable the 8259 PICs
     unsafe { interrupts::PICS.lock().initialize() };
+
+    // Set up the PS/2 controller before it can start raising interrupts.
+    if let Err(err) = unsafe { ps2::CONTROLLER.lock().init() } {
+        log::warn!("PS/2 controller initialization failed: {:?}", err);
+    }
+    init_mouse();
+    serial::init();
     x86_64::instructions::interrupts::enable();
 
     let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
@@ -43,10 +60,44 @@ pub fn init(
 
     allocator::init_heap(&mut mem_map, &mut frame_allocator)
         .expect("heap initialization failed");
+    vga::init();
+
+    #[cfg(feature = "framebuffer")]
+    init_framebuffer(&mut mem_map, &mut frame_allocator);
 
     (mem_map, frame_allocator)
 }
 
+/// Switches to drawing the console on a framebuffer, if there's one that can
+/// be used.
+#[cfg(feature = "framebuffer")]
+fn init_framebuffer(
+    mapper: &mut OffsetPageTable,
+    frame_allocator: &mut memory::BootInfoFrameAllocator,
+) {
+    match framebuffer::init(mapper, frame_allocator) {
+        Ok(()) | Err(framebuffer::FramebufferError::NotFound) => {},
+        Err(err) => log::warn!("framebuffer initialization failed: {:?}", err),
+    }
+}
+
+/// Turns on the PS/2 mouse, if there is one, and lets its interrupts through.
+fn init_mouse() {
+    let mut controller = ps2::CONTROLLER.lock();
+    match controller.device(ps2::Ps2Port::Second) {
+        Some(device) if device.is_mouse() => {},
+        _ => return,
+    }
+
+    match controller.init_mouse() {
+        Ok(_) => {
+            drop(controller);
+            interrupts::unmask_irq(12);
+        },
+        Err(err) => log::warn!("PS/2 mouse initialization failed: {:?}", err),
+    }
+}
+
 /// Enter a low-power infinite loop.
 pub fn halt() -> ! {
     loop {
@@ -66,6 +117,7 @@ fn test_kernel_start(boot_info: &'static bootloader::BootInfo) -> ! {
 }
 
 pub trait Test {
+    fn name(&self) -> &'static str;
     fn run(&self);
 }
 
@@ -73,16 +125,39 @@ impl<T> Test for T
 where
     T: Fn(),
 {
+    fn name(&self) -> &'static str {
+        core::any::type_name::<T>()
+    }
+
     fn run(&self) {
-        serial_print!("{}...\t", core::any::type_name::<T>());
+        serial_print!("{}...\t", self.name());
         self();
         serial_println!("[ok]");
     }
 }
 
+/// Runs the tests, or just the ones that the host asks for over serial. See
+/// the `harness` module for how to ask.
 pub fn test_runner(tests: &[&dyn Test]) {
-    serial_println!("Running {} tests", tests.len());
-    for test in tests {
+    // keep the results readable, rather than mixed in with everything printed
+    console::unregister("serial");
+    // breakpoints in tests would otherwise wait for GDB to resume them
+    serial::assign(serial::Role::Debugger, None)
+        .expect("taking the debugger role away failed");
+
+    let filter = if harness::is_available() {
+        harness::read_commands(tests)
+    }
+    else {
+        None
+    };
+    let selected = |test: &&&dyn Test| match &filter {
+        Some(filter) => test.name().contains(filter.as_str()),
+        None => true,
+    };
+
+    serial_println!("Running {} tests", tests.iter().filter(selected).count());
+    for test in tests.iter().filter(selected) {
         test.run();
     }
     exit_qemu(QemuExitCode::Success);
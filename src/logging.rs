@@ -0,0 +1,201 @@
+//! Kernel logging, through the macros of the `log` crate (`error!`, `warn!`,
+//! `info!`, `debug!` and `trace!`).
+//!
+//! Each record is stamped with the time since boot and the module it came
+//! from, written to a virtual console of its own (shown with Alt+F3), and kept
+//! in a ring buffer that can be read back with `dmesg`. If a serial port has
+//! been given the log role, records are sent to it as well. Which records are
+//! let through is decided by a default level, which can be overridden for any
+//! module while the kernel is running.
+//!
+//! Modules are named by their path, without the name of the crate, so
+//! `andromeda_os::task::keyboard` is `task::keyboard`. A module's level also
+//! applies to the modules inside it, unless they have a level of their own.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::{self, Write};
+
+use log::{Level, LevelFilter, Log, Metadata, Record, SetLoggerError};
+
+use crate::console::RingSink;
+use crate::serial::{self, Role};
+use crate::sync::IrqSafeMutex;
+use crate::{interrupts, vga};
+
+/// The level that modules without one of their own are logged at.
+pub const DEFAULT_LEVEL: LevelFilter = LevelFilter::Info;
+
+const CRATE_PREFIX: &str = "andromeda_os::";
+
+/// The console that records are written to.
+const LOG_CONSOLE: usize = 2;
+
+static LOGGER: Logger = Logger;
+static DMESG: RingSink = RingSink::new();
+static FILTERS: IrqSafeMutex<Filters> =
+    IrqSafeMutex::new(Filters { default: DEFAULT_LEVEL, modules: Vec::new() });
+
+struct Filters {
+    default: LevelFilter,
+    modules: Vec<(String, LevelFilter)>,
+}
+
+impl Filters {
+    /// Returns the level of the most specific filter that covers the target.
+    fn level(&self, target: &str) -> LevelFilter {
+        let target = module_name(target);
+        self.modules
+            .iter()
+            .filter(|(module, _)| is_within(target, module))
+            .max_by_key(|(module, _)| module.len())
+            .map_or(self.default, |&(_, level)| level)
+    }
+
+    /// Lets the `log` macros skip anything that no filter would let through.
+    fn update_max_level(&self) {
+        let max = self.modules.iter().map(|&(_, level)| level);
+        log::set_max_level(max.fold(self.default, Ord::max));
+    }
+}
+
+struct Logger;
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= FILTERS.lock().level(metadata.target())
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let uptime = interrupts::uptime_ms();
+        let (seconds, ms) = (uptime / 1000, uptime % 1000);
+        let level = record.level();
+        let module = module_name(record.target());
+        let write_line = |write: &dyn Fn(fmt::Arguments)| {
+            write(format_args!(
+                "[{:>5}.{:03}] {:<5} {}: {}\n",
+                seconds,
+                ms,
+                level,
+                module,
+                record.args()
+            ))
+        };
+        write_line(&|line| DMESG.write_fmt(line));
+        write_line(&|line| serial::write_fmt(Role::Log, line));
+
+        let color = match level {
+            Level::Error => "\x1b[91m",
+            Level::Warn => "\x1b[93m",
+            Level::Info => "",
+            Level::Debug | Level::Trace => "\x1b[90m",
+        };
+        let _ = writeln!(
+            vga::console(LOG_CONSOLE).lock(),
+            "{}[{:>5}.{:03}] {:<5} {}: {}\x1b[0m",
+            color,
+            seconds,
+            ms,
+            level,
+            module,
+            record.args()
+        );
+    }
+
+    fn flush(&self) {}
+}
+
+/// Starts handling records from the `log` macros. Anything logged before this
+/// is thrown away.
+pub fn init() -> Result<(), SetLoggerError> {
+    log::set_logger(&LOGGER)?;
+    FILTERS.lock().update_max_level();
+    Ok(())
+}
+
+/// Returns the level that modules without one of their own are logged at.
+pub fn level() -> LevelFilter {
+    FILTERS.lock().default
+}
+
+/// Changes the level that modules without one of their own are logged at.
+pub fn set_level(level: LevelFilter) {
+    let mut filters = FILTERS.lock();
+    filters.default = level;
+    filters.update_max_level();
+}
+
+/// Logs a module, and the modules inside it, at the given level.
+pub fn set_module_level(module: &str, level: LevelFilter) {
+    let module = module_name(module);
+    let mut filters = FILTERS.lock();
+    match filters.modules.iter_mut().find(|(name, _)| name == module) {
+        Some(filter) => filter.1 = level,
+        None => filters.modules.push((String::from(module), level)),
+    }
+    filters.update_max_level();
+}
+
+/// Goes back to logging a module at the level of whichever module it's in,
+/// returning false if it didn't have a level of its own.
+pub fn reset_module_level(module: &str) -> bool {
+    let module = module_name(module);
+    let mut filters = FILTERS.lock();
+    let len = filters.modules.len();
+    filters.modules.retain(|(name, _)| name != module);
+    filters.update_max_level();
+    filters.modules.len() != len
+}
+
+/// Returns the modules that have a level of their own.
+pub fn module_levels() -> Vec<(String, LevelFilter)> {
+    FILTERS.lock().modules.clone()
+}
+
+/// Returns the most recent records, oldest first.
+pub fn dmesg() -> String {
+    DMESG.contents()
+}
+
+/// Throws away the records kept for `dmesg`.
+pub fn clear_dmesg() {
+    DMESG.clear();
+}
+
+fn module_name(target: &str) -> &str {
+    target.strip_prefix(CRATE_PREFIX).unwrap_or(target)
+}
+
+/// Returns true if the module is, or is inside, the other module.
+fn is_within(module: &str, other: &str) -> bool {
+    match module.strip_prefix(other) {
+        Some(rest) => rest.is_empty() || rest.starts_with("::"),
+        None => false,
+    }
+}
+
+
+#[test_case]
+fn test_filters() {
+    use log::log_enabled;
+
+    set_module_level("andromeda_os::test_filters", LevelFilter::Trace);
+    set_module_level("test_filters::quiet", LevelFilter::Off);
+    assert!(log_enabled!(target: "test_filters::inner", Level::Trace));
+    assert!(!log_enabled!(target: "test_filters::quiet", Level::Error));
+    assert!(!log_enabled!(target: "test_filtersx", Level::Debug));
+
+    log::debug!(target: "test_filters::inner", "kept");
+    log::error!(target: "test_filters::quiet", "not kept");
+    assert!(dmesg().ends_with("DEBUG test_filters::inner: kept\n"));
+
+    assert!(reset_module_level("test_filters"));
+    assert!(!reset_module_level("test_filters"));
+    assert!(!log_enabled!(target: "test_filters::inner", Level::Trace));
+    assert!(reset_module_level("test_filters::quiet"));
+    assert!(module_levels().is_empty());
+}
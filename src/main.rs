@@ -8,9 +8,10 @@ extern crate alloc;
 
 use core::panic::PanicInfo;
 
-use andromeda_os::task::{keyboard, Executor};
+use andromeda_os::task::keyboard::LineReader;
+use andromeda_os::task::{input, mouse, Executor};
 use andromeda_os::vga::Color::*;
-use andromeda_os::{halt, println, vga};
+use andromeda_os::{framebuffer, halt, println, shell, vga};
 use bootloader::BootInfo;
 
 fn main() {
@@ -24,10 +25,38 @@ fn main() {
     println!("{:?}", s);
 
     let mut executor = Executor::new(100);
-    executor.spawn(keyboard::print_keypresses());
+    executor.spawn(input::dispatch()).expect("failed to spawn input task");
+    executor
+        .spawn(shell::run(executor.spawner()))
+        .expect("failed to spawn shell task");
+    executor
+        .spawn(shell::run_serial(executor.spawner()))
+        .expect("failed to spawn serial shell task");
+    executor.spawn(echo()).expect("failed to spawn echo task");
+    executor.spawn(mouse::draw_cursor()).expect("failed to spawn mouse task");
+    executor
+        .spawn(framebuffer::redraw())
+        .expect("failed to spawn framebuffer task");
     executor.run()
 }
 
+/// The console that `echo` runs on, which is shown with Alt+F4.
+const ECHO_CONSOLE: usize = 3;
+
+/// Echoes back each line typed on a console of its own, as a task to try the
+/// virtual consoles out with.
+async fn echo() {
+    let console = vga::console(ECHO_CONSOLE);
+    let mut reader = LineReader::on_console(ECHO_CONSOLE);
+
+    loop {
+        console.lock().write_str("echo> ");
+        let line = reader.read_line().await;
+        console.lock().write_str(&line);
+        console.lock().write_str("\n");
+    }
+}
+
 bootloader::entry_point!(kernel_start);
 fn kernel_start(boot_info: &'static BootInfo) -> ! {
     andromeda_os::init(boot_info);
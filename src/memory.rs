@@ -1,10 +1,15 @@
 use bootloader::bootinfo::{MemoryMap, MemoryRegionType};
+use conquer_once::spin::OnceCell;
 use x86_64::registers::control::Cr3;
 use x86_64::structures::paging::{
-    FrameAllocator, Mapper, OffsetPageTable, Page, PhysFrame, Size4KiB,
+    FrameAllocator, Mapper, OffsetPageTable, Page, PageTable, PageTableFlags,
+    PhysFrame, Size4KiB,
 };
 use x86_64::{PhysAddr, VirtAddr};
 
+/// Where the physical memory is mapped, as given to `init`.
+static PHYSICAL_MEMORY_OFFSET: OnceCell<VirtAddr> = OnceCell::uninit();
+
 /// Initialize a new OffsetPageTable.
 ///
 /// # Safety
@@ -13,6 +18,7 @@ use x86_64::{PhysAddr, VirtAddr};
 pub unsafe fn init(
     physical_memory_offset: VirtAddr,
 ) -> OffsetPageTable<'static> {
+    let _ = PHYSICAL_MEMORY_OFFSET.try_init_once(|| physical_memory_offset);
     let (table_frame, _) = Cr3::read();
 
     let start_address = table_frame.start_address();
@@ -22,6 +28,42 @@ pub unsafe fn init(
     OffsetPageTable::new(level_4_table, physical_memory_offset)
 }
 
+/// Returns the physical address that the virtual address is mapped to by the
+/// active page table, or None if it isn't mapped or `init` hasn't been called.
+///
+/// Unlike `Translate::translate_addr`, this only reads the page tables, so it
+/// doesn't need the `OffsetPageTable` that `init` returned.
+pub fn translate(addr: VirtAddr) -> Option<PhysAddr> {
+    let offset = *PHYSICAL_MEMORY_OFFSET.try_get().ok()?;
+    let (mut frame, _) = Cr3::read();
+
+    let indexes =
+        [addr.p4_index(), addr.p3_index(), addr.p2_index(), addr.p1_index()];
+    for (level, &index) in indexes.iter().enumerate() {
+        let table_addr = offset + frame.start_address().as_u64();
+        // `init` was told that all physical memory is mapped at the offset
+        let table: &PageTable = unsafe { &*table_addr.as_ptr() };
+        let entry = &table[index];
+
+        let flags = entry.flags();
+        if !flags.contains(PageTableFlags::PRESENT) {
+            return None;
+        }
+        if flags.contains(PageTableFlags::HUGE_PAGE) {
+            // a 1 GiB page in the level 3 table, or 2 MiB in the level 2 table
+            let page_mask = match level {
+                1 => 0x3fff_ffff,
+                2 => 0x1f_ffff,
+                _ => return None,
+            };
+            return Some(entry.addr() + (addr.as_u64() & page_mask));
+        }
+        frame = PhysFrame::containing_address(entry.addr());
+    }
+
+    Some(frame.start_address() + u64::from(addr.page_offset()))
+}
+
 pub fn create_example_mapping(
     page: Page, mem_map: &mut OffsetPageTable,
     frame_allocator: &mut impl FrameAllocator<Size4KiB>,
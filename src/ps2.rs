@@ -0,0 +1,536 @@
+//! A driver for the 8042 PS/2 controller, and the devices attached to it.
+
+use x86_64::instructions::port::{Port, PortReadOnly, PortWriteOnly};
+
+use crate::sync::IrqSafeMutex;
+use crate::task::keyboard::{self, ScancodeSetKind};
+
+pub static CONTROLLER: IrqSafeMutex<Controller> =
+    IrqSafeMutex::new(Controller::new());
+
+/// The number of times to poll the status register before giving up.
+const TIMEOUT: usize = 100_000;
+/// The number of times to ask a device to repeat itself before giving up.
+const RETRIES: usize = 3;
+
+// status register bits
+const OUTPUT_FULL: u8 = 1 << 0;
+const INPUT_FULL: u8 = 1 << 1;
+
+// configuration byte bits
+const CONFIG_PORT1_IRQ: u8 = 1 << 0;
+const CONFIG_PORT2_IRQ: u8 = 1 << 1;
+const CONFIG_PORT2_CLOCK_OFF: u8 = 1 << 5;
+const CONFIG_TRANSLATION: u8 = 1 << 6;
+
+// controller commands
+const READ_CONFIG: u8 = 0x20;
+const WRITE_CONFIG: u8 = 0x60;
+const DISABLE_PORT2: u8 = 0xa7;
+const ENABLE_PORT2: u8 = 0xa8;
+const TEST_PORT2: u8 = 0xa9;
+const SELF_TEST: u8 = 0xaa;
+const TEST_PORT1: u8 = 0xab;
+const DISABLE_PORT1: u8 = 0xad;
+const ENABLE_PORT1: u8 = 0xae;
+const WRITE_PORT2: u8 = 0xd4;
+const PULSE_RESET: u8 = 0xfe;
+
+// device commands and responses
+const SET_LEDS: u8 = 0xed;
+const SCANCODE_SET: u8 = 0xf0;
+const IDENTIFY: u8 = 0xf2;
+const SET_TYPEMATIC: u8 = 0xf3;
+const SET_SAMPLE_RATE: u8 = 0xf3; // same command, as understood by mice
+const ENABLE_SCANNING: u8 = 0xf4;
+const DISABLE_SCANNING: u8 = 0xf5;
+const SET_DEFAULTS: u8 = 0xf6;
+const ACK: u8 = 0xfa;
+const RESEND: u8 = 0xfe;
+
+const SELF_TEST_PASSED: u8 = 0x55;
+const PORT_TEST_PASSED: u8 = 0x00;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ps2Error {
+    /// The controller or a device didn't respond in time.
+    Timeout,
+    /// The controller's self-test returned something other than 0x55.
+    SelfTestFailed(u8),
+    /// A port's interface test returned the given error code.
+    PortTestFailed(Ps2Port, u8),
+    /// A device replied to a command with something other than an ACK.
+    UnexpectedResponse(u8),
+    /// The controller only has one port.
+    NoSecondPort,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ps2Port {
+    First,
+    Second,
+}
+
+impl Ps2Port {
+    fn index(self) -> usize {
+        match self {
+            Ps2Port::First => 0,
+            Ps2Port::Second => 1,
+        }
+    }
+
+    /// The port's interrupt bit in the controller's configuration byte.
+    fn irq_bit(self) -> u8 {
+        match self {
+            Ps2Port::First => CONFIG_PORT1_IRQ,
+            Ps2Port::Second => CONFIG_PORT2_IRQ,
+        }
+    }
+}
+
+/// The kind of device attached to a port, as reported by its identify command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeviceType {
+    /// An AT keyboard, which doesn't reply to the identify command.
+    AtKeyboard,
+    /// An MF2 keyboard, whose scancodes are being translated to set 1.
+    Mf2KeyboardTranslated,
+    Mf2Keyboard,
+    StandardMouse,
+    /// A mouse with a scroll wheel.
+    ScrollMouse,
+    FiveButtonMouse,
+    /// A device that isn't recognised, holding whatever it identified as.
+    Unknown(u8, u8),
+}
+
+impl DeviceType {
+    fn from_id(id: &[u8]) -> Self {
+        match id {
+            [] => DeviceType::AtKeyboard,
+            [0xab, 0x41] | [0xab, 0xc1] => DeviceType::Mf2KeyboardTranslated,
+            [0xab, 0x83] => DeviceType::Mf2Keyboard,
+            [0x00] => DeviceType::StandardMouse,
+            [0x03] => DeviceType::ScrollMouse,
+            [0x04] => DeviceType::FiveButtonMouse,
+            [a] => DeviceType::Unknown(*a, 0),
+            [a, b, ..] => DeviceType::Unknown(*a, *b),
+        }
+    }
+
+    pub fn is_keyboard(self) -> bool {
+        matches!(
+            self,
+            DeviceType::AtKeyboard
+                | DeviceType::Mf2KeyboardTranslated
+                | DeviceType::Mf2Keyboard
+        )
+    }
+
+    pub fn is_mouse(self) -> bool {
+        matches!(
+            self,
+            DeviceType::StandardMouse
+                | DeviceType::ScrollMouse
+                | DeviceType::FiveButtonMouse
+        )
+    }
+}
+
+/// The keyboard's indicator lights.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Leds {
+    pub scroll_lock: bool,
+    pub num_lock:    bool,
+    pub caps_lock:   bool,
+}
+
+impl Leds {
+    fn as_u8(self) -> u8 {
+        (self.scroll_lock as u8)
+            | (self.num_lock as u8) << 1
+            | (self.caps_lock as u8) << 2
+    }
+}
+
+/// How quickly a held key repeats.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Typematic {
+    /// From 0 (30 repeats per second) to 31 (2 repeats per second).
+    pub rate:  u8,
+    /// From 0 (250ms before repeating starts) to 3 (1000ms).
+    pub delay: u8,
+}
+
+impl Typematic {
+    fn as_u8(self) -> u8 {
+        (self.delay & 0b11) << 5 | (self.rate & 0b1_1111)
+    }
+}
+
+pub struct Controller {
+    data:         Port<u8>,
+    status:       PortReadOnly<u8>,
+    command:      PortWriteOnly<u8>,
+    dual_channel: bool,
+    devices:      [Option<DeviceType>; 2],
+}
+
+impl Controller {
+    const fn new() -> Self {
+        Controller {
+            data:         Port::new(0x60),
+            status:       PortReadOnly::new(0x64),
+            command:      PortWriteOnly::new(0x64),
+            dual_channel: false,
+            devices:      [None, None],
+        }
+    }
+
+    /// Resets and tests the controller, then identifies the devices attached to
+    /// it.
+    ///
+    /// Only the first port is left enabled, with its interrupt turned on and
+    /// scancode translation on, so the keyboard keeps sending set 1. The second
+    /// port is left disabled until a driver enables it.
+    ///
+    /// # Safety
+    /// Unsafe because the caller must make sure that nothing else is using the
+    /// controller, and that no other code relies on its current state.
+    pub unsafe fn init(&mut self) -> Result<(), Ps2Error> {
+        self.write_command(DISABLE_PORT1)?;
+        self.write_command(DISABLE_PORT2)?;
+        self.flush();
+
+        // keep interrupts off until the devices are ready
+        let mut config = self.read_config()?;
+        config &= !(CONFIG_PORT1_IRQ | CONFIG_PORT2_IRQ);
+        config |= CONFIG_TRANSLATION;
+        self.write_config(config)?;
+
+        self.write_command(SELF_TEST)?;
+        match self.read_data()? {
+            SELF_TEST_PASSED => {},
+            result => return Err(Ps2Error::SelfTestFailed(result)),
+        }
+        // some controllers reset themselves during the self-test
+        self.write_config(config)?;
+
+        // the second port's clock turns on with it, if there is one
+        self.write_command(ENABLE_PORT2)?;
+        self.dual_channel = self.read_config()? & CONFIG_PORT2_CLOCK_OFF == 0;
+        self.write_command(DISABLE_PORT2)?;
+
+        self.test_port(Ps2Port::First)?;
+        if self.dual_channel && self.test_port(Ps2Port::Second).is_err() {
+            self.dual_channel = false;
+        }
+
+        // an empty port is fine; there's just nothing to identify
+        self.enable_port(Ps2Port::First)?;
+        self.devices[0] = self.identify(Ps2Port::First).ok();
+        if self.dual_channel {
+            self.enable_port(Ps2Port::Second)?;
+            self.devices[1] = self.identify(Ps2Port::Second).ok();
+
+            // keep the second device quiet until a driver asks for it
+            let _ = self.send(Ps2Port::Second, &[DISABLE_SCANNING]);
+            self.disable_port(Ps2Port::Second)?;
+        }
+
+        self.write_config(config | CONFIG_PORT1_IRQ)
+    }
+
+    pub fn has_second_port(&self) -> bool {
+        self.dual_channel
+    }
+
+    /// Returns the device found on the given port during `init`.
+    pub fn device(&self, port: Ps2Port) -> Option<DeviceType> {
+        self.devices[port.index()]
+    }
+
+    pub fn enable_port(&mut self, port: Ps2Port) -> Result<(), Ps2Error> {
+        match port {
+            Ps2Port::First => self.write_command(ENABLE_PORT1),
+            Ps2Port::Second if self.dual_channel =>
+                self.write_command(ENABLE_PORT2),
+            Ps2Port::Second => Err(Ps2Error::NoSecondPort),
+        }
+    }
+
+    pub fn disable_port(&mut self, port: Ps2Port) -> Result<(), Ps2Error> {
+        match port {
+            Ps2Port::First => self.write_command(DISABLE_PORT1),
+            Ps2Port::Second if self.dual_channel =>
+                self.write_command(DISABLE_PORT2),
+            Ps2Port::Second => Err(Ps2Error::NoSecondPort),
+        }
+    }
+
+    /// Asks the device on the given port what it is.
+    pub fn identify(&mut self, port: Ps2Port) -> Result<DeviceType, Ps2Error> {
+        self.with_irq_masked(port, |controller| {
+            controller.send(port, &[DISABLE_SCANNING])?;
+            controller.send(port, &[IDENTIFY])?;
+
+            // the ID is up to two bytes long, and may not be there at all
+            let mut id = [0; 2];
+            let mut len = 0;
+            while len < id.len() {
+                match controller.read_data() {
+                    Ok(byte) => id[len] = byte,
+                    Err(_) => break,
+                }
+                len += 1;
+            }
+
+            controller.send(port, &[ENABLE_SCANNING])?;
+            Ok(DeviceType::from_id(&id[..len]))
+        })
+    }
+
+    /// Sets the keyboard's indicator lights.
+    pub fn set_leds(&mut self, leds: Leds) -> Result<(), Ps2Error> {
+        self.with_irq_masked(Ps2Port::First, |controller| {
+            controller.send(Ps2Port::First, &[SET_LEDS, leds.as_u8()])
+        })
+    }
+
+    /// Sets how quickly held keys repeat.
+    pub fn set_typematic(
+        &mut self, typematic: Typematic,
+    ) -> Result<(), Ps2Error> {
+        self.with_irq_masked(Ps2Port::First, |controller| {
+            controller.send(Ps2Port::First, &[SET_TYPEMATIC, typematic.as_u8()])
+        })
+    }
+
+    /// Switches the keyboard to the given scancode set, and tells the keyboard
+    /// task to decode it.
+    ///
+    /// The keyboard is always put into set 2; set 1 is then produced by the
+    /// controller's translation, which is what most keyboards support best.
+    pub fn set_scancode_set(
+        &mut self, set: ScancodeSetKind,
+    ) -> Result<(), Ps2Error> {
+        self.with_irq_masked(Ps2Port::First, |controller| {
+            controller.send(Ps2Port::First, &[SCANCODE_SET, 2])?;
+
+            let mut config = controller.read_config()?;
+            match set {
+                ScancodeSetKind::Set1 => config |= CONFIG_TRANSLATION,
+                ScancodeSetKind::Set2 => config &= !CONFIG_TRANSLATION,
+            }
+            // `with_irq_masked` restores the IRQ bit afterwards
+            controller.write_config(config)
+        })?;
+
+        keyboard::set_scancode_set(set);
+        Ok(())
+    }
+
+    /// Sets up the mouse on the second port and turns on its interrupt, so that
+    /// it starts sending movement packets on IRQ12.
+    ///
+    /// Scroll wheel (IntelliMouse) mode is turned on if the mouse supports it;
+    /// returns the mouse's type afterwards, which says whether it did.
+    pub fn init_mouse(&mut self) -> Result<DeviceType, Ps2Error> {
+        let port = Ps2Port::Second;
+        self.enable_port(port)?;
+
+        let device = self.with_irq_masked(port, |controller| {
+            controller.send(port, &[SET_DEFAULTS])?;
+
+            // this sequence of sample rates is the IntelliMouse knock
+            for &rate in &[200, 100, 80] {
+                controller.send(port, &[SET_SAMPLE_RATE, rate])?;
+            }
+
+            // identifying leaves data reporting turned on
+            controller.identify(port)
+        })?;
+
+        self.devices[port.index()] = Some(device);
+        self.set_irq(port, true)?;
+        Ok(device)
+    }
+
+    /// Resets the computer by pulsing the CPU's reset line, which the
+    /// controller is wired to. Only returns if that didn't work.
+    pub fn reset_system(&mut self) -> Ps2Error {
+        if let Err(err) = self.write_command(PULSE_RESET) {
+            return err;
+        }
+
+        // give the reset some time to happen
+        for _ in 0..TIMEOUT {
+            core::hint::spin_loop();
+        }
+        Ps2Error::Timeout
+    }
+
+    /// Sends a command (and any argument bytes) to a device, checking that
+    /// each byte is acknowledged.
+    pub fn send(
+        &mut self, port: Ps2Port, bytes: &[u8],
+    ) -> Result<(), Ps2Error> {
+        for &byte in bytes {
+            let mut response = RESEND;
+            for _ in 0..RETRIES {
+                self.write_device(port, byte)?;
+                response = self.read_data()?;
+                if response != RESEND {
+                    break;
+                }
+            }
+            if response != ACK {
+                return Err(Ps2Error::UnexpectedResponse(response));
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads a byte that a device sent in response to a command.
+    pub fn read_response(&mut self) -> Result<u8, Ps2Error> {
+        self.read_data()
+    }
+
+    /// Runs `f` with the given port's interrupt turned off, so that replies to
+    /// commands can be polled for without the interrupt handler taking them.
+    pub fn with_irq_masked<T>(
+        &mut self, port: Ps2Port,
+        f: impl FnOnce(&mut Self) -> Result<T, Ps2Error>,
+    ) -> Result<T, Ps2Error> {
+        let irq_bit = port.irq_bit();
+        let config = self.read_config()?;
+        self.write_config(config & !irq_bit)?;
+        let result = f(self);
+        let new_config = self.read_config()?;
+        self.write_config(new_config | (config & irq_bit))?;
+        result
+    }
+
+    /// Turns the interrupt for the given port on or off.
+    pub fn set_irq(
+        &mut self, port: Ps2Port, enabled: bool,
+    ) -> Result<(), Ps2Error> {
+        let irq_bit = port.irq_bit();
+        let config = self.read_config()?;
+        if enabled {
+            self.write_config(config | irq_bit)
+        }
+        else {
+            self.write_config(config & !irq_bit)
+        }
+    }
+
+    fn test_port(&mut self, port: Ps2Port) -> Result<(), Ps2Error> {
+        self.write_command(match port {
+            Ps2Port::First => TEST_PORT1,
+            Ps2Port::Second => TEST_PORT2,
+        })?;
+        match self.read_data()? {
+            PORT_TEST_PASSED => Ok(()),
+            result => Err(Ps2Error::PortTestFailed(port, result)),
+        }
+    }
+
+    fn read_config(&mut self) -> Result<u8, Ps2Error> {
+        self.write_command(READ_CONFIG)?;
+        self.read_data()
+    }
+
+    fn write_config(&mut self, config: u8) -> Result<(), Ps2Error> {
+        self.write_command(WRITE_CONFIG)?;
+        self.write_data(config)
+    }
+
+    fn write_device(
+        &mut self, port: Ps2Port, byte: u8,
+    ) -> Result<(), Ps2Error> {
+        if port == Ps2Port::Second {
+            if !self.dual_channel {
+                return Err(Ps2Error::NoSecondPort);
+            }
+            self.write_command(WRITE_PORT2)?;
+        }
+        self.write_data(byte)
+    }
+
+    fn write_command(&mut self, command: u8) -> Result<(), Ps2Error> {
+        self.wait_for(|status| status & INPUT_FULL == 0)?;
+        unsafe { self.command.write(command) };
+        Ok(())
+    }
+
+    fn write_data(&mut self, byte: u8) -> Result<(), Ps2Error> {
+        self.wait_for(|status| status & INPUT_FULL == 0)?;
+        unsafe { self.data.write(byte) };
+        Ok(())
+    }
+
+    fn read_data(&mut self) -> Result<u8, Ps2Error> {
+        self.wait_for(|status| status & OUTPUT_FULL != 0)?;
+        Ok(unsafe { self.data.read() })
+    }
+
+    /// Throws away anything waiting in the output buffer.
+    fn flush(&mut self) {
+        while unsafe { self.status.read() } & OUTPUT_FULL != 0 {
+            unsafe { self.data.read() };
+        }
+    }
+
+    fn wait_for(&mut self, ready: impl Fn(u8) -> bool) -> Result<(), Ps2Error> {
+        for _ in 0..TIMEOUT {
+            if ready(unsafe { self.status.read() }) {
+                return Ok(());
+            }
+            core::hint::spin_loop();
+        }
+        Err(Ps2Error::Timeout)
+    }
+}
+
+
+#[test_case]
+fn test_device_types() {
+    assert_eq!(DeviceType::from_id(&[]), DeviceType::AtKeyboard);
+    assert_eq!(
+        DeviceType::from_id(&[0xab, 0x41]),
+        DeviceType::Mf2KeyboardTranslated
+    );
+    assert_eq!(
+        DeviceType::from_id(&[0xab, 0xc1]),
+        DeviceType::Mf2KeyboardTranslated
+    );
+    assert_eq!(DeviceType::from_id(&[0xab, 0x83]), DeviceType::Mf2Keyboard);
+    assert_eq!(DeviceType::from_id(&[0x00]), DeviceType::StandardMouse);
+    assert_eq!(DeviceType::from_id(&[0x03]), DeviceType::ScrollMouse);
+    assert_eq!(DeviceType::from_id(&[0x04]), DeviceType::FiveButtonMouse);
+    assert_eq!(DeviceType::from_id(&[0x12]), DeviceType::Unknown(0x12, 0));
+    assert_eq!(
+        DeviceType::from_id(&[0xab, 0x12]),
+        DeviceType::Unknown(0xab, 0x12)
+    );
+
+    assert!(DeviceType::Mf2Keyboard.is_keyboard());
+    assert!(!DeviceType::Mf2Keyboard.is_mouse());
+    assert!(DeviceType::ScrollMouse.is_mouse());
+    assert!(!DeviceType::Unknown(0xab, 0x12).is_keyboard());
+}
+
+#[test_case]
+fn test_command_bytes() {
+    let leds =
+        Leds { scroll_lock: true, num_lock: false, caps_lock: true };
+    assert_eq!(leds.as_u8(), 0b101);
+    assert_eq!(Leds::default().as_u8(), 0);
+
+    assert_eq!(Typematic { rate: 0, delay: 0 }.as_u8(), 0);
+    assert_eq!(Typematic { rate: 31, delay: 3 }.as_u8(), 0b0111_1111);
+    assert_eq!(Typematic { rate: 0x0b, delay: 1 }.as_u8(), 0b0010_1011);
+    // out of range values don't spill into the other field
+    assert_eq!(Typematic { rate: 0xff, delay: 0 }.as_u8(), 0b0001_1111);
+}
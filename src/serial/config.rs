@@ -0,0 +1,159 @@
+use core::convert::TryFrom;
+use core::fmt;
+use core::str::FromStr;
+
+/// The rate that the UART's baud rate divisor divides.
+const BASE_BAUD: u32 = 115_200;
+
+/// The settings a serial port runs at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Config {
+    pub baud:    u32,
+    pub framing: Framing,
+}
+
+impl Config {
+    pub const fn new(baud: u32, framing: Framing) -> Self {
+        Config { baud, framing }
+    }
+
+    /// Returns the UART's divisor for the baud rate, if it's one that the UART
+    /// can run at exactly.
+    pub fn divisor(&self) -> Option<u16> {
+        if self.baud == 0 || BASE_BAUD % self.baud != 0 {
+            return None;
+        }
+        u16::try_from(BASE_BAUD / self.baud).ok()
+    }
+
+    /// Returns true if the UART can be set up to use this configuration.
+    pub fn is_valid(&self) -> bool {
+        self.divisor().is_some() && self.framing.is_valid()
+    }
+}
+
+impl Default for Config {
+    /// 38400 baud, 8N1.
+    fn default() -> Self {
+        Config::new(38400, Framing::default())
+    }
+}
+
+impl fmt::Display for Config {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", self.baud, self.framing)
+    }
+}
+
+/// How each character is sent: the number of data bits, the parity bit and
+/// the number of stop bits. Written the usual way, such as `8N1`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Framing {
+    /// From 5 to 8.
+    pub data_bits: u8,
+    pub parity:    Parity,
+    /// Either 1 or 2. With 5 data bits, 2 means one and a half.
+    pub stop_bits: u8,
+}
+
+impl Framing {
+    pub const fn new(data_bits: u8, parity: Parity, stop_bits: u8) -> Self {
+        Framing { data_bits, parity, stop_bits }
+    }
+
+    pub fn is_valid(&self) -> bool {
+        (5..=8).contains(&self.data_bits) && (1..=2).contains(&self.stop_bits)
+    }
+
+    /// Returns the value of the UART's line control register.
+    pub(super) fn line_control(&self) -> u8 {
+        let parity = match self.parity {
+            Parity::None => 0b000,
+            Parity::Odd => 0b001,
+            Parity::Even => 0b011,
+            Parity::Mark => 0b101,
+            Parity::Space => 0b111,
+        };
+        (self.data_bits - 5) | (self.stop_bits - 1) << 2 | parity << 3
+    }
+}
+
+impl Default for Framing {
+    fn default() -> Self {
+        Framing::new(8, Parity::None, 1)
+    }
+}
+
+impl fmt::Display for Framing {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let parity = match self.parity {
+            Parity::None => 'N',
+            Parity::Odd => 'O',
+            Parity::Even => 'E',
+            Parity::Mark => 'M',
+            Parity::Space => 'S',
+        };
+        write!(f, "{}{}{}", self.data_bits, parity, self.stop_bits)
+    }
+}
+
+impl FromStr for Framing {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, ()> {
+        let bytes = s.as_bytes();
+        if bytes.len() != 3 {
+            return Err(());
+        }
+
+        let parity = match bytes[1].to_ascii_uppercase() {
+            b'N' => Parity::None,
+            b'O' => Parity::Odd,
+            b'E' => Parity::Even,
+            b'M' => Parity::Mark,
+            b'S' => Parity::Space,
+            _ => return Err(()),
+        };
+        let framing = Framing::new(
+            bytes[0].wrapping_sub(b'0'),
+            parity,
+            bytes[2].wrapping_sub(b'0'),
+        );
+        if framing.is_valid() {
+            Ok(framing)
+        }
+        else {
+            Err(())
+        }
+    }
+}
+
+/// The parity bit sent after each character's data bits, if any.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Parity {
+    None,
+    Odd,
+    Even,
+    /// Always set.
+    Mark,
+    /// Always clear.
+    Space,
+}
+
+
+#[test_case]
+fn test_config() {
+    let config = Config::default();
+    assert_eq!((config.divisor(), config.framing.line_control()), (Some(3), 3));
+    assert_eq!(alloc::format!("{}", config), "38400 8N1");
+    assert_eq!(Config::new(9600, config.framing).divisor(), Some(12));
+    assert!(!Config::new(1000, config.framing).is_valid());
+    assert!(!Config::new(0, config.framing).is_valid());
+    assert!(!Config::new(1, config.framing).is_valid());
+
+    let framing: Framing = "7e2".parse().unwrap();
+    assert_eq!(framing, Framing::new(7, Parity::Even, 2));
+    assert_eq!(framing.line_control(), 0b0001_1110);
+    assert!("9N1".parse::<Framing>().is_err());
+    assert!("8X1".parse::<Framing>().is_err());
+}
@@ -0,0 +1,74 @@
+use alloc::string::String;
+
+use futures_util::stream::StreamExt;
+
+use super::{SerialStream, SerialWriter};
+
+/// The longest line that can be typed.
+const MAX_LINE_LEN: usize = 256;
+
+const CTRL_C: u8 = 0x03;
+const BACKSPACE: u8 = 0x08;
+const DELETE: u8 = 0x7f;
+
+/// Reads lines typed at the host's terminal, echoing them back to it as
+/// they're edited.
+///
+/// Only printable ASCII can be typed. Backspace removes the last character,
+/// and Ctrl+C throws the line away. Only one of these can ever be created, as
+/// it reads from a `SerialStream`.
+pub struct LineReader {
+    input:    SerialStream,
+    output:   SerialWriter,
+    /// Whether the last line was ended by a carriage return, so that a line
+    /// feed straight after it can be ignored.
+    after_cr: bool,
+}
+
+impl LineReader {
+    pub fn new() -> Self {
+        LineReader {
+            input:    SerialStream::new(),
+            output:   SerialWriter,
+            after_cr: false,
+        }
+    }
+
+    /// Waits for a line to be typed and submitted with Enter. A line that was
+    /// thrown away with Ctrl+C is returned empty.
+    pub async fn read_line(&mut self) -> String {
+        let mut line = String::new();
+
+        while let Some(byte) = self.input.next().await {
+            let after_cr =
+                core::mem::replace(&mut self.after_cr, byte == b'\r');
+            match byte {
+                b'\n' if after_cr => {},
+                b'\r' | b'\n' => break,
+                BACKSPACE | DELETE =>
+                    if line.pop().is_some() {
+                        self.output.write_all(b"\x08 \x08").await;
+                    },
+                CTRL_C => {
+                    self.output.write_all(b"^C").await;
+                    line.clear();
+                    break;
+                },
+                0x20..=0x7e if line.len() < MAX_LINE_LEN => {
+                    line.push(byte as char);
+                    self.output.write_all(&[byte]).await;
+                },
+                _ => {},
+            }
+        }
+
+        self.output.write_all(b"\r\n").await;
+        line
+    }
+}
+
+impl Default for LineReader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,499 @@
+//! The PC's serial ports, COM1 to COM4. COM1 is how the kernel talks to the
+//! host when it's run under QEMU with `-serial stdio`.
+//!
+//! Each port is looked for and checked with a loopback self-test when it's
+//! first used, then set up with the default `Config`, which can be changed
+//! with `SerialPort::configure`. Ports are given jobs with `assign`: the
+//! console port gets console output and `serial_print!`, and is what a
+//! `SerialStream` reads from; the log port, if there is one, gets a copy of
+//! every log record; and the debugger port is where the `gdb` stub talks to
+//! GDB.
+//!
+//! Once `init` has been called, output is queued and sent from each port's
+//! interrupt as the UART makes room for it, so printing doesn't have to wait
+//! for the line. Anything written while interrupts are disabled, or with
+//! `serial_print!`, is sent straight away instead, so that it gets out even if
+//! the kernel is about to stop.
+
+mod config;
+mod line;
+mod stream;
+pub mod uart;
+
+use core::fmt;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use lazy_static::lazy_static;
+
+pub use self::config::{Config, Framing, Parity};
+pub use self::line::LineReader;
+pub use self::stream::{dropped_bytes, SerialStream, SerialWriter};
+use self::uart::Uart;
+use crate::console::Sink;
+use crate::sync::IrqSafeMutex;
+
+/// The number of serial ports, COM1 to COM4, which are numbered from 0 here.
+pub const PORT_COUNT: usize = 4;
+
+/// COM1's base port.
+pub const COM1: u16 = 0x3f8;
+
+const BASES: [u16; PORT_COUNT] = [COM1, 0x2f8, 0x3e8, 0x2e8];
+/// The lines on the PIC that the ports interrupt on. COM1 and COM3 share one,
+/// as do COM2 and COM4.
+const IRQS: [u8; PORT_COUNT] = [4, 3, 4, 3];
+
+/// The number of bytes of output that can be waiting to be sent on each port.
+const TX_QUEUE_SIZE: usize = 4096;
+
+/// Stands for no port in `ROLES`.
+const NO_PORT: usize = usize::MAX;
+
+/// What GDB sends to stop the kernel while it runs.
+const CTRL_C: u8 = 0x03;
+
+/// The port given each role, indexed by `Role`.
+static ROLES: [AtomicUsize; 3] =
+    [AtomicUsize::new(0), AtomicUsize::new(NO_PORT), AtomicUsize::new(NO_PORT)];
+
+lazy_static! {
+    static ref PORTS: [IrqSafeMutex<SerialPort>; PORT_COUNT] = [
+        IrqSafeMutex::new(SerialPort::new(0)),
+        IrqSafeMutex::new(SerialPort::new(1)),
+        IrqSafeMutex::new(SerialPort::new(2)),
+        IrqSafeMutex::new(SerialPort::new(3)),
+    ];
+    pub static ref SERIAL1: &'static IrqSafeMutex<SerialPort> = port(0);
+}
+
+/// Starts handling the interrupts of every port that was found, so that their
+/// output can be queued rather than waited on, and their input can be read.
+pub fn init() {
+    for (index, port) in PORTS.iter().enumerate() {
+        let mut port = port.lock();
+        if port.is_present() {
+            port.enable_interrupts();
+            drop(port);
+            crate::interrupts::unmask_irq(IRQS[index]);
+        }
+    }
+}
+
+/// Returns the port with the given index, where COM1 is 0.
+///
+/// Panics if `index` isn't less than `PORT_COUNT`.
+pub fn port(index: usize) -> &'static IrqSafeMutex<SerialPort> {
+    &PORTS[index]
+}
+
+/// The reason a serial port can't be used for something.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SerialError {
+    NoSuchPort,
+    /// There's no UART at the port.
+    NotFound,
+    /// The UART didn't pass its loopback self-test, so it's not used.
+    SelfTestFailed,
+    /// The baud rate or framing isn't one the UART can use.
+    InvalidConfig,
+}
+
+impl fmt::Display for SerialError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SerialError::NoSuchPort => write!(f, "no such port"),
+            SerialError::NotFound => write!(f, "port not found"),
+            SerialError::SelfTestFailed => write!(f, "port failed self-test"),
+            SerialError::InvalidConfig =>
+                write!(f, "unsupported baud rate or framing"),
+        }
+    }
+}
+
+/// A job that a serial port can be given.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    /// Console output and input. COM1 to begin with.
+    Console,
+    /// A copy of every log record.
+    Log,
+    /// Kept for a debugger to talk to the host over.
+    Debugger,
+}
+
+impl Role {
+    pub const ALL: [Role; 3] = [Role::Console, Role::Log, Role::Debugger];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Role::Console => "console",
+            Role::Log => "log",
+            Role::Debugger => "debugger",
+        }
+    }
+}
+
+/// Gives a role to the port with the given index, or takes it away from
+/// whichever port has it. A port can have more than one role.
+pub fn assign(role: Role, index: Option<usize>) -> Result<(), SerialError> {
+    if let Some(index) = index {
+        if index >= PORT_COUNT {
+            return Err(SerialError::NoSuchPort);
+        }
+        if let Some(err) = port(index).lock().error() {
+            return Err(err);
+        }
+    }
+    ROLES[role as usize].store(index.unwrap_or(NO_PORT), Ordering::Relaxed);
+    Ok(())
+}
+
+/// Returns the index of the port with the given role, if there is one.
+pub fn assigned(role: Role) -> Option<usize> {
+    match ROLES[role as usize].load(Ordering::Relaxed) {
+        NO_PORT => None,
+        index => Some(index),
+    }
+}
+
+/// Returns the port with the given role, if there is one.
+pub fn role_port(role: Role) -> Option<&'static IrqSafeMutex<SerialPort>> {
+    assigned(role).map(port)
+}
+
+/// Writes to the port with the given role, if there is one, queueing as much
+/// as possible.
+pub fn write_fmt(role: Role, args: fmt::Arguments) {
+    struct Writer(&'static IrqSafeMutex<SerialPort>);
+
+    impl fmt::Write for Writer {
+        fn write_str(&mut self, s: &str) -> fmt::Result {
+            write_queued(self.0, s);
+            Ok(())
+        }
+    }
+
+    if let Some(port) = role_port(role) {
+        let _ = fmt::Write::write_fmt(&mut Writer(port), args);
+    }
+}
+
+/// Queues as much of the string as there's room for, and sends the rest
+/// straight away.
+fn write_queued(port: &IrqSafeMutex<SerialPort>, s: &str) {
+    // the queue is emptied by the interrupt handler, so there's no point
+    // queueing anything if that can't run
+    let can_queue = x86_64::instructions::interrupts::are_enabled();
+
+    let mut port = port.lock();
+    let queued = if can_queue { port.queue(s.as_bytes()) } else { 0 };
+    port.send(&s.as_bytes()[queued..]);
+}
+
+/// A serial port, along with the output waiting to be sent from its interrupt
+/// handler.
+///
+/// Anything written to a port that can't be used is thrown away.
+pub struct SerialPort {
+    index:      usize,
+    uart:       Uart,
+    config:     Config,
+    /// Why the port can't be used, if it can't.
+    error:      Option<SerialError>,
+    tx:         TxQueue,
+    /// Whether the port's interrupt is being handled, so that output can be
+    /// queued.
+    interrupts: bool,
+}
+
+impl SerialPort {
+    /// Looks for the port, and if it's there, sets it up with the default
+    /// configuration and tests it.
+    fn new(index: usize) -> Self {
+        // each port is only created once
+        let mut uart = unsafe { Uart::new(BASES[index]) };
+        let config = Config::default();
+
+        let error = if uart.detect() {
+            uart.init(&config);
+            if uart.self_test() {
+                None
+            }
+            else {
+                Some(SerialError::SelfTestFailed)
+            }
+        }
+        else {
+            Some(SerialError::NotFound)
+        };
+
+        SerialPort {
+            index,
+            uart,
+            config,
+            error,
+            tx: TxQueue::new(),
+            interrupts: false,
+        }
+    }
+
+    /// Returns the port's index, where COM1 is 0.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Returns true if the port was found and passed its self-test.
+    pub fn is_present(&self) -> bool {
+        self.error.is_none()
+    }
+
+    /// Returns the reason the port can't be used, if it can't.
+    pub fn error(&self) -> Option<SerialError> {
+        self.error
+    }
+
+    pub fn config(&self) -> Config {
+        self.config
+    }
+
+    /// Changes the port's baud rate and framing, after sending anything
+    /// that's been queued.
+    pub fn configure(&mut self, config: Config) -> Result<(), SerialError> {
+        if let Some(err) = self.error {
+            return Err(err);
+        }
+        if !config.is_valid() {
+            return Err(SerialError::InvalidConfig);
+        }
+
+        self.flush();
+        self.uart.init(&config);
+        self.config = config;
+        if self.interrupts {
+            self.uart.set_interrupts(true, false);
+        }
+        Ok(())
+    }
+
+    /// Queues as many of the bytes as there's room for, returning how many
+    /// were queued. Everything is sent straight away if the port's interrupt
+    /// isn't being handled.
+    pub fn queue(&mut self, bytes: &[u8]) -> usize {
+        if !self.interrupts {
+            self.send(bytes);
+            return bytes.len();
+        }
+
+        let queued = self.tx.push_slice(bytes);
+        if queued > 0 {
+            self.uart.set_interrupts(true, true);
+        }
+        queued
+    }
+
+    /// Sends the bytes after anything that's been queued, waiting for them all
+    /// to go.
+    pub fn send(&mut self, bytes: &[u8]) {
+        if !self.is_present() {
+            return;
+        }
+        self.flush();
+        for &byte in bytes {
+            self.uart.send(byte);
+        }
+    }
+
+    /// Sends everything that's been queued, waiting for it to go.
+    pub fn flush(&mut self) {
+        while let Some(byte) = self.tx.pop() {
+            self.uart.send(byte);
+        }
+    }
+
+    /// Returns true if nothing is waiting to be sent.
+    pub fn is_flushed(&self) -> bool {
+        self.tx.is_empty()
+    }
+
+    /// Returns the next received byte, if there is one. This is for polling
+    /// with interrupts disabled, since otherwise the port's interrupt handler
+    /// takes whatever's received.
+    pub fn receive(&mut self) -> Option<u8> {
+        if !self.is_present() {
+            return None;
+        }
+        self.uart.receive()
+    }
+
+    fn enable_interrupts(&mut self) {
+        self.interrupts = true;
+        self.uart.set_interrupts(true, !self.tx.is_empty());
+    }
+
+    /// Returns true if GDB asked for the kernel to be stopped.
+    fn handle_interrupt(&mut self) -> bool {
+        self.uart.interrupt_id();
+        let is_console = assigned(Role::Console) == Some(self.index);
+        let is_debugger = assigned(Role::Debugger) == Some(self.index);
+        let mut stop = false;
+        while let Some(byte) = self.uart.receive() {
+            // GDB only sends Ctrl+C while the kernel runs, and the stub reads
+            // everything else itself once it's stopped
+            if is_debugger && byte == CTRL_C {
+                stop = true;
+            }
+            else if is_console {
+                stream::push_byte(byte);
+            }
+        }
+
+        if !self.tx.is_empty() && self.uart.is_tx_empty() {
+            for _ in 0..uart::FIFO_SIZE {
+                match self.tx.pop() {
+                    Some(byte) => self.uart.send_unchecked(byte),
+                    None => break,
+                }
+            }
+            stream::on_sent();
+        }
+        if self.tx.is_empty() {
+            self.uart.set_interrupts(true, false);
+        }
+        stop
+    }
+}
+
+impl fmt::Write for SerialPort {
+    /// Sends the string straight away, waiting for it to go.
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.send(s.as_bytes());
+        Ok(())
+    }
+}
+
+/// A fixed-size queue of bytes waiting to be sent.
+struct TxQueue {
+    bytes: [u8; TX_QUEUE_SIZE],
+    start: usize,
+    len:   usize,
+}
+
+impl TxQueue {
+    const fn new() -> Self {
+        TxQueue { bytes: [0; TX_QUEUE_SIZE], start: 0, len: 0 }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Adds as many of the bytes as there's room for, returning how many were
+    /// added.
+    fn push_slice(&mut self, bytes: &[u8]) -> usize {
+        let count = bytes.len().min(TX_QUEUE_SIZE - self.len);
+        for &byte in &bytes[..count] {
+            self.bytes[(self.start + self.len) % TX_QUEUE_SIZE] = byte;
+            self.len += 1;
+        }
+        count
+    }
+
+    fn pop(&mut self) -> Option<u8> {
+        if self.len == 0 {
+            return None;
+        }
+        let byte = self.bytes[self.start];
+        self.start = (self.start + 1) % TX_QUEUE_SIZE;
+        self.len -= 1;
+        Some(byte)
+    }
+}
+
+/// Called by the interrupt handler for the given IRQ, which is shared by two
+/// of the ports. Returns true if GDB asked for the kernel to be stopped, which
+/// the handler does once it's done with the PIC.
+pub(crate) fn on_interrupt(irq: u8) -> bool {
+    let mut stop = false;
+    for (index, port) in PORTS.iter().enumerate() {
+        if IRQS[index] == irq {
+            let mut port = port.lock();
+            if port.interrupts {
+                stop |= port.handle_interrupt();
+            }
+        }
+    }
+    stop
+}
+
+/// Sends console output to the console port.
+pub struct SerialSink;
+
+impl Sink for SerialSink {
+    fn name(&self) -> &'static str {
+        "serial"
+    }
+
+    fn write_str(&self, s: &str) {
+        if let Some(port) = role_port(Role::Console) {
+            write_queued(port, s);
+        }
+    }
+}
+
+#[doc(hidden)]
+pub fn _print(args: ::core::fmt::Arguments) {
+    use core::fmt::Write;
+
+    if let Some(port) = role_port(Role::Console) {
+        port.lock().write_fmt(args).expect("Printing to serial failed");
+    }
+}
+
+/// Prints to the host through the console's serial port.
+#[macro_export]
+macro_rules! serial_print {
+    ($($arg:tt)*) => {
+        $crate::serial::_print(format_args!($($arg)*));
+    };
+}
+
+/// Prints to the host through the console's serial port, appending a newline.
+#[macro_export]
+macro_rules! serial_println {
+    () => ($crate::serial_print!("\n"));
+    ($fmt:expr) => ($crate::serial_print!(concat!($fmt, "\n")));
+    ($fmt:expr, $($arg:tt)*) => ($crate::serial_print!(
+            concat!($fmt, "\n"), $($arg)*));
+}
+
+
+#[test_case]
+fn test_tx_queue() {
+    let mut queue = TxQueue::new();
+    assert_eq!(queue.push_slice(&[1; TX_QUEUE_SIZE - 1]), TX_QUEUE_SIZE - 1);
+    assert_eq!(queue.pop(), Some(1));
+    assert_eq!(queue.push_slice(&[2, 3, 4]), 2);
+
+    // the queue has wrapped around, and still comes out in order
+    for _ in 0..TX_QUEUE_SIZE - 2 {
+        assert_eq!(queue.pop(), Some(1));
+    }
+    assert_eq!(
+        (queue.pop(), queue.pop(), queue.pop()),
+        (Some(2), Some(3), None)
+    );
+    assert!(queue.is_empty());
+}
+
+#[test_case]
+fn test_roles() {
+    assert_eq!(assigned(Role::Console), Some(0));
+    assert_eq!(
+        assign(Role::Log, Some(PORT_COUNT)),
+        Err(SerialError::NoSuchPort)
+    );
+    assert_eq!(assign(Role::Log, Some(0)), Ok(()));
+    assert_eq!(assigned(Role::Log), Some(0));
+    assert_eq!(assign(Role::Log, None), Ok(()));
+    assert_eq!(assigned(Role::Log), None);
+}
@@ -0,0 +1,171 @@
+use core::pin::Pin;
+use core::sync::atomic::{AtomicU64, Ordering};
+use core::task::{Context, Poll};
+
+use conquer_once::spin::OnceCell;
+use crossbeam_queue::ArrayQueue;
+use futures_util::future;
+use futures_util::stream::Stream;
+use futures_util::task::AtomicWaker;
+
+use super::Role;
+
+/// The number of received bytes that can be waiting to be read.
+const INPUT_QUEUE_SIZE: usize = 256;
+
+static INPUT_QUEUE: OnceCell<ArrayQueue<u8>> = OnceCell::uninit();
+static RX_WAKER: AtomicWaker = AtomicWaker::new();
+static TX_WAKER: AtomicWaker = AtomicWaker::new();
+static DROPPED_BYTES: AtomicU64 = AtomicU64::new(0);
+
+/// Queues a byte received on the console port for the `SerialStream`.
+///
+/// Like `keyboard::push_scancode`, this must not block or allocate, so bytes
+/// that can't be queued are only counted, and reported later.
+pub(super) fn push_byte(byte: u8) {
+    let pushed = match INPUT_QUEUE.try_get() {
+        Ok(queue) => queue.push(byte).is_ok(),
+        Err(_) => false,
+    };
+
+    if pushed {
+        RX_WAKER.wake();
+    }
+    else {
+        DROPPED_BYTES.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Called when a port has sent some of its queued output.
+pub(super) fn on_sent() {
+    TX_WAKER.wake();
+}
+
+/// Returns the number of received bytes that have been dropped, either
+/// because the queue was full or because there wasn't one yet.
+pub fn dropped_bytes() -> u64 {
+    DROPPED_BYTES.load(Ordering::Relaxed)
+}
+
+/// A stream of the bytes received on the console port.
+///
+/// Only one of these can ever be created. Bytes received before then are
+/// dropped.
+pub struct SerialStream {
+    /// The value of `dropped_bytes` when it was last reported.
+    reported_dropped: u64,
+}
+
+impl SerialStream {
+    pub fn new() -> Self {
+        INPUT_QUEUE
+            .try_init_once(|| ArrayQueue::new(INPUT_QUEUE_SIZE))
+            .expect("SerialStream::new should only be called once");
+        SerialStream { reported_dropped: dropped_bytes() }
+    }
+}
+
+impl Default for SerialStream {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Stream for SerialStream {
+    type Item = u8;
+
+    fn poll_next(
+        mut self: Pin<&mut Self>, cx: &mut Context,
+    ) -> Poll<Option<u8>> {
+        let queue = INPUT_QUEUE.try_get().expect("input queue not initialized");
+
+        let dropped = dropped_bytes();
+        if dropped != self.reported_dropped {
+            log::warn!(
+                "serial input queue full; dropped {} bytes",
+                dropped - self.reported_dropped
+            );
+            self.reported_dropped = dropped;
+        }
+
+        if let Some(byte) = queue.pop() {
+            return Poll::Ready(Some(byte));
+        }
+
+        RX_WAKER.register(cx.waker());
+        match queue.pop() {
+            Some(byte) => {
+                RX_WAKER.take();
+                Poll::Ready(Some(byte))
+            },
+            None => Poll::Pending,
+        }
+    }
+}
+
+/// Writes to the console port from a task, waiting for room in the queue
+/// rather than for the bytes to be sent. Output is thrown away if there's no
+/// console port.
+///
+/// The methods are the same as those of the `futures` crate's `AsyncWrite`,
+/// which isn't available without `std`.
+#[derive(Debug, Default)]
+pub struct SerialWriter;
+
+impl SerialWriter {
+    /// Queues as much of `buf` as there's room for, returning how much was
+    /// queued, or waits for there to be room.
+    pub fn poll_write(&mut self, cx: &mut Context, buf: &[u8]) -> Poll<usize> {
+        let port = match super::role_port(Role::Console) {
+            Some(port) => port,
+            None => return Poll::Ready(buf.len()),
+        };
+        if buf.is_empty() {
+            return Poll::Ready(0);
+        }
+        match port.lock().queue(buf) {
+            0 => {},
+            queued => return Poll::Ready(queued),
+        }
+
+        TX_WAKER.register(cx.waker());
+        match port.lock().queue(buf) {
+            0 => Poll::Pending,
+            queued => {
+                TX_WAKER.take();
+                Poll::Ready(queued)
+            },
+        }
+    }
+
+    /// Waits for everything queued to have been sent.
+    pub fn poll_flush(&mut self, cx: &mut Context) -> Poll<()> {
+        let port = match super::role_port(Role::Console) {
+            Some(port) => port,
+            None => return Poll::Ready(()),
+        };
+        if port.lock().is_flushed() {
+            return Poll::Ready(());
+        }
+
+        TX_WAKER.register(cx.waker());
+        if port.lock().is_flushed() {
+            TX_WAKER.take();
+            return Poll::Ready(());
+        }
+        Poll::Pending
+    }
+
+    /// Queues all of `buf`, waiting for room as needed.
+    pub async fn write_all(&mut self, mut buf: &[u8]) {
+        while !buf.is_empty() {
+            let queued = future::poll_fn(|cx| self.poll_write(cx, buf)).await;
+            buf = &buf[queued..];
+        }
+    }
+
+    /// Waits for everything queued to have been sent.
+    pub async fn flush(&mut self) {
+        future::poll_fn(|cx| self.poll_flush(cx)).await
+    }
+}
@@ -0,0 +1,166 @@
+//! The registers of the 16550 UART, the chip behind each of the PC's serial
+//! ports.
+
+use core::fmt;
+
+use x86_64::instructions::port::Port;
+
+use super::Config;
+
+/// The number of bytes the transmitter's FIFO holds, which can all be written
+/// at once when it's empty.
+pub const FIFO_SIZE: usize = 16;
+
+// registers, as offsets from the port's base address
+const DATA: u16 = 0;
+const INTERRUPT_ENABLE: u16 = 1;
+/// The interrupt identification register when read, and the FIFO control
+/// register when written.
+const INTERRUPT_ID: u16 = 2;
+const LINE_CONTROL: u16 = 3;
+const MODEM_CONTROL: u16 = 4;
+const LINE_STATUS: u16 = 5;
+/// A spare register, which is only there to be written to and read back.
+const SCRATCH: u16 = 7;
+
+/// Makes the first two registers hold the baud rate divisor.
+const DIVISOR_LATCH: u8 = 0x80;
+/// Enables and clears both FIFOs, interrupting once 14 bytes are received.
+const FIFO_ENABLE: u8 = 0xc7;
+/// Sets DTR and RTS, and OUT2, which lets the UART's interrupts through to
+/// the PIC.
+const MODEM_READY: u8 = 0x0b;
+/// Connects the transmitter to the receiver, and keeps the UART off the line.
+const MODEM_LOOPBACK: u8 = 0x1e;
+
+const RX_INTERRUPT: u8 = 0x01;
+const TX_INTERRUPT: u8 = 0x02;
+
+const DATA_READY: u8 = 0x01;
+const TX_EMPTY: u8 = 0x20;
+
+/// The byte sent during the self-test.
+const TEST_BYTE: u8 = 0xae;
+/// How many times to check for the test byte to come back before giving up.
+const TEST_SPINS: usize = 10_000;
+
+/// A 16550 UART at the given base port.
+pub struct Uart {
+    base: u16,
+}
+
+impl Uart {
+    /// # Safety
+    ///
+    /// The caller has to make sure that there's a UART at the port, and that
+    /// nothing else is using it.
+    pub const unsafe fn new(base: u16) -> Self {
+        Uart { base }
+    }
+
+    /// Returns true if there seems to be a UART at the port, going by whether
+    /// its scratch register keeps what's written to it.
+    pub fn detect(&mut self) -> bool {
+        [0x5a, 0xa5].iter().all(|&value| {
+            self.write(SCRATCH, value);
+            self.read(SCRATCH) == value
+        })
+    }
+
+    /// Sets the port up with the given configuration, with its FIFOs enabled
+    /// and its interrupts off.
+    ///
+    /// Panics if the configuration isn't valid.
+    pub fn init(&mut self, config: &Config) {
+        let divisor = config.divisor().expect("invalid baud rate");
+        assert!(config.framing.is_valid(), "invalid framing");
+
+        self.write(INTERRUPT_ENABLE, 0);
+        self.write(LINE_CONTROL, DIVISOR_LATCH);
+        self.write(DATA, divisor as u8);
+        self.write(INTERRUPT_ENABLE, (divisor >> 8) as u8);
+        self.write(LINE_CONTROL, config.framing.line_control());
+        self.write(INTERRUPT_ID, FIFO_ENABLE);
+        self.write(MODEM_CONTROL, MODEM_READY);
+    }
+
+    /// Sends a byte to itself in loopback mode, returning true if it came
+    /// back. Anything waiting to be received is thrown away.
+    ///
+    /// The port's interrupts should be off.
+    pub fn self_test(&mut self) -> bool {
+        self.write(MODEM_CONTROL, MODEM_LOOPBACK);
+        while self.receive().is_some() {}
+        self.send(TEST_BYTE);
+
+        let received = (0..TEST_SPINS).find_map(|_| self.receive());
+        self.write(MODEM_CONTROL, MODEM_READY);
+        received == Some(TEST_BYTE)
+    }
+
+    /// Chooses which interrupts the UART raises: when a byte's been
+    /// received, and when the transmitter is ready for more.
+    pub fn set_interrupts(&mut self, rx: bool, tx: bool) {
+        let mut enabled = 0;
+        if rx {
+            enabled |= RX_INTERRUPT;
+        }
+        if tx {
+            enabled |= TX_INTERRUPT;
+        }
+        self.write(INTERRUPT_ENABLE, enabled);
+    }
+
+    /// Reads the interrupt identification register, which acknowledges an
+    /// interrupt from the transmitter.
+    pub fn interrupt_id(&mut self) -> u8 {
+        self.read(INTERRUPT_ID)
+    }
+
+    /// Returns true if the transmitter's FIFO is empty, so that `FIFO_SIZE`
+    /// bytes can be written without waiting.
+    pub fn is_tx_empty(&mut self) -> bool {
+        self.read(LINE_STATUS) & TX_EMPTY != 0
+    }
+
+    /// Sends a byte, waiting for the transmitter to have room for it.
+    pub fn send(&mut self, byte: u8) {
+        while !self.is_tx_empty() {
+            core::hint::spin_loop();
+        }
+        self.write(DATA, byte);
+    }
+
+    /// Writes a byte without checking that there's room for it.
+    pub(super) fn send_unchecked(&mut self, byte: u8) {
+        self.write(DATA, byte);
+    }
+
+    /// Returns the next received byte, if there is one.
+    pub fn receive(&mut self) -> Option<u8> {
+        match self.read(LINE_STATUS) & DATA_READY {
+            0 => None,
+            _ => Some(self.read(DATA)),
+        }
+    }
+
+    fn read(&mut self, register: u16) -> u8 {
+        let mut port = Port::new(self.base + register);
+        unsafe { port.read() }
+    }
+
+    fn write(&mut self, register: u16, value: u8) {
+        let mut port = Port::new(self.base + register);
+        unsafe { port.write(value) }
+    }
+}
+
+impl fmt::Write for Uart {
+    /// Sends the string, waiting for each byte to go.
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for byte in s.bytes() {
+            self.send(byte);
+        }
+        Ok(())
+    }
+}
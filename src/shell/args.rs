@@ -0,0 +1,86 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseError {
+    /// A quote was opened but never closed.
+    UnterminatedQuote,
+    /// The line ended with a backslash.
+    TrailingEscape,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::UnterminatedQuote => write!(f, "unterminated quote"),
+            ParseError::TrailingEscape => write!(f, "nothing to escape"),
+        }
+    }
+}
+
+/// Splits a command line into words.
+///
+/// Words are separated by whitespace, unless it's inside single or double
+/// quotes. Outside of single quotes, a backslash includes the next character
+/// as-is.
+pub fn split(line: &str) -> Result<Vec<String>, ParseError> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut quote = None;
+    let mut chars = line.chars();
+
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some('\''), '\'') | (Some('"'), '"') => quote = None,
+            (Some('\''), c) => word.push(c),
+            (_, '\\') => match chars.next() {
+                Some(c) => word.push(c),
+                None => return Err(ParseError::TrailingEscape),
+            },
+            (Some(_), c) => word.push(c),
+            (None, '\'') | (None, '"') => quote = Some(c),
+            (None, c) if c.is_whitespace() => {
+                if in_word {
+                    words.push(core::mem::take(&mut word));
+                }
+                in_word = false;
+                continue;
+            },
+            (None, c) => word.push(c),
+        }
+        in_word = true;
+    }
+
+    if quote.is_some() {
+        return Err(ParseError::UnterminatedQuote);
+    }
+    if in_word {
+        words.push(word);
+    }
+    Ok(words)
+}
+
+
+#[test_case]
+fn test_split() {
+    use alloc::vec;
+
+    assert_eq!(
+        split("  echo  hello world "),
+        Ok(vec!["echo".into(), "hello".into(), "world".into()])
+    );
+    assert_eq!(
+        split(r#"echo "a  b" 'c "d"' e\ f """#),
+        Ok(vec![
+            "echo".into(),
+            "a  b".into(),
+            r#"c "d""#.into(),
+            "e f".into(),
+            "".into()
+        ])
+    );
+    assert_eq!(split("echo \"oops"), Err(ParseError::UnterminatedQuote));
+    assert_eq!(split("echo oops\\"), Err(ParseError::TrailingEscape));
+}
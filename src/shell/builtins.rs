@@ -0,0 +1,428 @@
+use alloc::format;
+use core::fmt::Write;
+
+use log::LevelFilter;
+
+use super::{Command, CommandError, Shell};
+use crate::serial::{self, Config, Role, SerialError};
+use crate::task::keyboard::{self, Layout};
+use crate::vga::tui::{Rect, ScrollRegion, StatusBar, Style, Window};
+use crate::vga::{self, Color, VGA_WRITER};
+use crate::{allocator, interrupts, logging, print, println, ps2};
+
+pub(super) const COMMANDS: &[Command] = &[
+    Command {
+        name:    "clear",
+        usage:   "",
+        summary: "clear the screen",
+        run:     clear,
+    },
+    Command {
+        name:    "color",
+        usage:   "<fg> [bg]",
+        summary: "change the text colour",
+        run:     color,
+    },
+    Command {
+        name:    "dashboard",
+        usage:   "",
+        summary: "show memory, tasks and logs on the second console",
+        run:     dashboard,
+    },
+    Command {
+        name:    "debug",
+        usage:   "",
+        summary: "stop and wait for GDB on the debugger port",
+        run:     debug,
+    },
+    Command {
+        name:    "dmesg",
+        usage:   "[-c]",
+        summary: "show the kernel log, or clear it with -c",
+        run:     dmesg,
+    },
+    Command {
+        name:    "echo",
+        usage:   "[text...]",
+        summary: "print some text",
+        run:     echo,
+    },
+    Command {
+        name:    "help",
+        usage:   "[command]",
+        summary: "list commands, or describe one",
+        run:     help,
+    },
+    Command {
+        name:    "layout",
+        usage:   "[name]",
+        summary: "show or change the keyboard layout",
+        run:     layout,
+    },
+    Command {
+        name:    "loglevel",
+        usage:   "[module] [level|reset]",
+        summary: "show or change which log records are kept",
+        run:     loglevel,
+    },
+    Command {
+        name:    "mem",
+        usage:   "",
+        summary: "show heap usage",
+        run:     mem,
+    },
+    Command {
+        name:    "reboot",
+        usage:   "",
+        summary: "restart the computer",
+        run:     reboot,
+    },
+    Command {
+        name:    "serial",
+        usage:   "[com<n> <baud> [framing] | assign <role> <com<n>|none>]",
+        summary: "show, configure or assign serial ports",
+        run:     serial,
+    },
+    Command {
+        name:    "tasks",
+        usage:   "",
+        summary: "list running tasks",
+        run:     tasks,
+    },
+    Command {
+        name:    "uptime",
+        usage:   "",
+        summary: "show the time since boot",
+        run:     uptime,
+    },
+];
+
+fn help(_: &Shell, args: &[&str]) -> Result<(), CommandError> {
+    match args {
+        [] =>
+            for command in super::commands() {
+                println!("{:<10}{}", command.name, command.summary);
+            },
+        [name] => {
+            let command = super::command(name).ok_or_else(|| {
+                CommandError::Failed(format!("no such command: {}", name))
+            })?;
+            println!("usage: {} {}", command.name, command.usage);
+            println!("{}", command.summary);
+        },
+        _ => return Err(CommandError::Usage),
+    }
+    Ok(())
+}
+
+fn echo(_: &Shell, args: &[&str]) -> Result<(), CommandError> {
+    for (i, arg) in args.iter().enumerate() {
+        if i > 0 {
+            print!(" ");
+        }
+        print!("{}", arg);
+    }
+    println!();
+    Ok(())
+}
+
+fn clear(_: &Shell, args: &[&str]) -> Result<(), CommandError> {
+    if !args.is_empty() {
+        return Err(CommandError::Usage);
+    }
+    VGA_WRITER.lock().clear();
+    Ok(())
+}
+
+fn color(_: &Shell, args: &[&str]) -> Result<(), CommandError> {
+    let parse = |name: &str| {
+        Color::from_name(name).ok_or_else(|| {
+            CommandError::Failed(format!("unknown colour: {}", name))
+        })
+    };
+
+    let (fg, bg) = match args {
+        [fg] => (parse(fg)?, Color::Black),
+        [fg, bg] => (parse(fg)?, parse(bg)?),
+        _ => {
+            print!("colours:");
+            for color in Color::ALL.iter() {
+                print!(" {}", color.name());
+            }
+            println!();
+            return Err(CommandError::Usage);
+        },
+    };
+    VGA_WRITER.lock().set_color(fg, bg);
+    Ok(())
+}
+
+fn layout(_: &Shell, args: &[&str]) -> Result<(), CommandError> {
+    match args {
+        [] =>
+            for &layout in Layout::ALL.iter() {
+                let current =
+                    if layout == keyboard::layout() { "*" } else { " " };
+                println!(
+                    "{} {:<10}{}",
+                    current,
+                    layout_arg(layout),
+                    layout.name()
+                );
+            },
+        [name] => {
+            let layout = Layout::ALL
+                .iter()
+                .copied()
+                .find(|&layout| layout_arg(layout).eq_ignore_ascii_case(name))
+                .ok_or_else(|| {
+                    CommandError::Failed(format!("unknown layout: {}", name))
+                })?;
+            keyboard::set_layout(layout);
+        },
+        _ => return Err(CommandError::Usage),
+    }
+    Ok(())
+}
+
+/// The name a layout is given by on the command line.
+fn layout_arg(layout: Layout) -> &'static str {
+    match layout {
+        Layout::Us104 => "us",
+        Layout::Uk105 => "uk",
+        Layout::De105 => "de",
+        Layout::Dvorak104 => "dvorak",
+    }
+}
+
+fn dmesg(_: &Shell, args: &[&str]) -> Result<(), CommandError> {
+    match args {
+        [] => print!("{}", logging::dmesg()),
+        ["-c"] => logging::clear_dmesg(),
+        _ => return Err(CommandError::Usage),
+    }
+    Ok(())
+}
+
+fn loglevel(_: &Shell, args: &[&str]) -> Result<(), CommandError> {
+    let parse = |name: &str| {
+        name.parse::<LevelFilter>().map_err(|_| {
+            CommandError::Failed(format!("unknown level: {}", name))
+        })
+    };
+
+    match args {
+        [] => {
+            println!("{:<24}{}", "(default)", logging::level());
+            for (module, level) in logging::module_levels() {
+                println!("{:<24}{}", module, level);
+            }
+        },
+        [level] => logging::set_level(parse(level)?),
+        [module, "reset"] =>
+            if !logging::reset_module_level(module) {
+                return Err(CommandError::Failed(format!(
+                    "no level set for {}",
+                    module
+                )));
+            },
+        [module, level] => logging::set_module_level(module, parse(level)?),
+        _ => return Err(CommandError::Usage),
+    }
+    Ok(())
+}
+
+fn mem(_: &Shell, args: &[&str]) -> Result<(), CommandError> {
+    if !args.is_empty() {
+        return Err(CommandError::Usage);
+    }
+
+    let stats = allocator::stats();
+    println!("heap:   {:>8} bytes", stats.size);
+    println!("used:   {:>8} bytes", stats.used);
+    println!("pooled: {:>8} bytes", stats.pooled);
+    println!("free:   {:>8} bytes", stats.free);
+    Ok(())
+}
+
+fn tasks(shell: &Shell, args: &[&str]) -> Result<(), CommandError> {
+    if !args.is_empty() {
+        return Err(CommandError::Usage);
+    }
+
+    println!("{:>4}  {:<8}{:>10}{:>16}", "ID", "PRIORITY", "POLLS", "CYCLES");
+    for (id, priority, stats) in shell.spawner().task_stats() {
+        println!(
+            "{:>4}  {:<8}{:>10}{:>16}",
+            id.as_u64(),
+            format!("{:?}", priority),
+            stats.polls,
+            stats.poll_cycles
+        );
+    }
+    Ok(())
+}
+
+fn serial(_: &Shell, args: &[&str]) -> Result<(), CommandError> {
+    let failed = |err: SerialError| CommandError::Failed(format!("{}", err));
+
+    match args {
+        [] =>
+            for index in 0..serial::PORT_COUNT {
+                let port = serial::port(index).lock();
+                let status = match port.error() {
+                    Some(SerialError::NotFound) => "not found".into(),
+                    Some(_) => "failed self-test".into(),
+                    None => format!("{}", port.config()),
+                };
+                drop(port);
+
+                print!("COM{}  {:<16}", index + 1, status);
+                for role in Role::ALL.iter() {
+                    if serial::assigned(*role) == Some(index) {
+                        print!(" {}", role.name());
+                    }
+                }
+                println!();
+            },
+        ["assign", role, target] => {
+            let role = Role::ALL
+                .iter()
+                .copied()
+                .find(|candidate| candidate.name().eq_ignore_ascii_case(role))
+                .ok_or_else(|| {
+                    CommandError::Failed(format!("unknown role: {}", role))
+                })?;
+            let index = match *target {
+                "none" => None,
+                target => Some(port_arg(target)?),
+            };
+            serial::assign(role, index).map_err(failed)?;
+        },
+        [port, baud, framing @ ..] if framing.len() <= 1 => {
+            let index = port_arg(port)?;
+            let baud = baud.parse().map_err(|_| {
+                CommandError::Failed(format!("invalid baud rate: {}", baud))
+            })?;
+            let mut config = Config { baud, ..Config::default() };
+            if let [framing] = framing {
+                config.framing = framing.parse().map_err(|_| {
+                    CommandError::Failed(format!(
+                        "invalid framing: {}",
+                        framing
+                    ))
+                })?;
+            }
+            serial::port(index).lock().configure(config).map_err(failed)?;
+        },
+        _ => return Err(CommandError::Usage),
+    }
+    Ok(())
+}
+
+/// Parses a serial port's name, such as `com1`, into its index.
+fn port_arg(name: &str) -> Result<usize, CommandError> {
+    let number = name
+        .get(..3)
+        .filter(|prefix| prefix.eq_ignore_ascii_case("com"))
+        .and_then(|_| name[3..].parse::<usize>().ok())
+        .filter(|number| (1..=serial::PORT_COUNT).contains(number));
+    match number {
+        Some(number) => Ok(number - 1),
+        None => Err(CommandError::Failed(format!("unknown port: {}", name))),
+    }
+}
+
+fn debug(_: &Shell, args: &[&str]) -> Result<(), CommandError> {
+    if !args.is_empty() {
+        return Err(CommandError::Usage);
+    }
+    let index = serial::assigned(Role::Debugger).ok_or_else(|| {
+        CommandError::Failed("no port has the debugger role".into())
+    })?;
+
+    println!("waiting for GDB on COM{}", index + 1);
+    x86_64::instructions::interrupts::int3();
+    Ok(())
+}
+
+/// The console that `dashboard` draws on.
+const DASHBOARD_CONSOLE: usize = 1;
+
+fn dashboard(shell: &Shell, args: &[&str]) -> Result<(), CommandError> {
+    if !args.is_empty() {
+        return Err(CommandError::Usage);
+    }
+
+    let (main, bar) = Rect::SCREEN.split_rows(vga::BUFFER_HEIGHT - 1);
+    let (top, log) = main.split_rows(main.height / 2);
+    let (memory, tasks) = top.split_cols(28);
+    let memory = Window::new(memory, "memory");
+    let tasks = Window::new(tasks, "tasks");
+    let log = Window::new(log, "log");
+
+    let mut memory_text = ScrollRegion::new(memory.content(), Style::NORMAL);
+    let stats = allocator::stats();
+    let _ = writeln!(memory_text, "heap:   {:>8} bytes", stats.size);
+    let _ = writeln!(memory_text, "used:   {:>8} bytes", stats.used);
+    let _ = writeln!(memory_text, "pooled: {:>8} bytes", stats.pooled);
+    let _ = write!(memory_text, "free:   {:>8} bytes", stats.free);
+
+    let mut task_text = ScrollRegion::new(tasks.content(), Style::NORMAL);
+    let _ = write!(task_text, "{:>4}  {:<8}{:>10}", "ID", "PRIORITY", "POLLS");
+    for (id, priority, stats) in shell.spawner().task_stats() {
+        let _ = write!(
+            task_text,
+            "\n{:>4}  {:<8}{:>10}",
+            id.as_u64(),
+            format!("{:?}", priority),
+            stats.polls
+        );
+    }
+
+    // only the most recent records fit, and the rest scroll out
+    let mut log_text = ScrollRegion::new(log.content(), Style::NORMAL);
+    let _ = log_text.write_str(&logging::dmesg());
+
+    let mut status = StatusBar::new(bar.row);
+    status.left = format!("up {}s", interrupts::uptime_ms() / 1000);
+    status.right = "Alt+F1: back to the shell".into();
+
+    let mut writer = vga::console(DASHBOARD_CONSOLE).lock();
+    writer.clear();
+    memory.draw(&mut writer);
+    memory_text.draw(&mut writer);
+    tasks.draw(&mut writer);
+    task_text.draw(&mut writer);
+    log.draw(&mut writer);
+    log_text.draw(&mut writer);
+    status.draw(&mut writer);
+    drop(writer);
+
+    vga::switch_console(DASHBOARD_CONSOLE);
+    Ok(())
+}
+
+fn uptime(_: &Shell, args: &[&str]) -> Result<(), CommandError> {
+    if !args.is_empty() {
+        return Err(CommandError::Usage);
+    }
+
+    let seconds = interrupts::uptime_ms() / 1000;
+    println!(
+        "up {}:{:02}:{:02}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    );
+    Ok(())
+}
+
+fn reboot(_: &Shell, args: &[&str]) -> Result<(), CommandError> {
+    if !args.is_empty() {
+        return Err(CommandError::Usage);
+    }
+
+    let err = ps2::CONTROLLER.lock().reset_system();
+    Err(CommandError::Failed(format!("reset failed: {:?}", err)))
+}
@@ -0,0 +1,191 @@
+//! An interactive shell, read from the keyboard or from COM1.
+//!
+//! Commands are kept in a global registry, so other modules can add their own
+//! with `register`.
+
+pub mod args;
+mod builtins;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+
+use lazy_static::lazy_static;
+use spin::Mutex;
+
+use crate::serial::{self, SerialWriter};
+use crate::task::keyboard::LineReader;
+use crate::task::Spawner;
+use crate::{print, println};
+
+const PROMPT: &str = "$ ";
+
+lazy_static! {
+    /// Kept sorted by name.
+    static ref COMMANDS: Mutex<Vec<Command>> = {
+        let mut commands = builtins::COMMANDS.to_vec();
+        commands.sort_by_key(|command| command.name);
+        Mutex::new(commands)
+    };
+}
+
+/// The function that runs a command, given the shell and the command's
+/// arguments (not including its name).
+pub type CommandFn = fn(&Shell, &[&str]) -> Result<(), CommandError>;
+
+/// A command that can be run from the shell.
+#[derive(Clone, Copy)]
+pub struct Command {
+    pub name:    &'static str,
+    /// The command's arguments, as shown by `help`.
+    pub usage:   &'static str,
+    /// A one-line description of what the command does.
+    pub summary: &'static str,
+    pub run:     CommandFn,
+}
+
+/// The reason a command failed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CommandError {
+    /// The command was given the wrong arguments.
+    Usage,
+    Failed(String),
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CommandError::Usage => write!(f, "invalid arguments"),
+            CommandError::Failed(reason) => write!(f, "{}", reason),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegisterError {
+    /// A command with the same name is already registered.
+    AlreadyRegistered,
+}
+
+/// Adds a command to the shell.
+pub fn register(command: Command) -> Result<(), RegisterError> {
+    let mut commands = COMMANDS.lock();
+    match commands.binary_search_by_key(&command.name, |c| c.name) {
+        Ok(_) => Err(RegisterError::AlreadyRegistered),
+        Err(index) => {
+            commands.insert(index, command);
+            Ok(())
+        },
+    }
+}
+
+/// Returns the command with the given name, if there is one.
+pub fn command(name: &str) -> Option<Command> {
+    let commands = COMMANDS.lock();
+    let index = commands.binary_search_by_key(&name, |c| c.name).ok()?;
+    Some(commands[index])
+}
+
+/// Returns every registered command, sorted by name.
+pub fn commands() -> Vec<Command> {
+    COMMANDS.lock().clone()
+}
+
+/// Reads commands from the keyboard and runs them, forever.
+pub async fn run(spawner: Spawner) {
+    let mut shell = Shell::new(spawner);
+    let mut reader = LineReader::new();
+
+    loop {
+        print!("{}", PROMPT);
+        let line = reader.read_line().await;
+        shell.execute(&line);
+    }
+}
+
+/// Reads commands from COM1 and runs them, forever, so that the kernel can be
+/// driven from the host's terminal without a keyboard.
+///
+/// Command output goes to every console sink, as it does for `run`, but the
+/// prompt and the line being typed are only sent to COM1.
+pub async fn run_serial(spawner: Spawner) {
+    let mut shell = Shell::new(spawner);
+    let mut reader = serial::LineReader::new();
+    let mut output = SerialWriter;
+
+    loop {
+        output.write_all(PROMPT.as_bytes()).await;
+        let line = reader.read_line().await;
+        shell.execute(&line);
+    }
+}
+
+/// The state that commands have access to.
+pub struct Shell {
+    spawner: Spawner,
+}
+
+impl Shell {
+    pub fn new(spawner: Spawner) -> Self {
+        Shell { spawner }
+    }
+
+    /// Returns a handle for spawning tasks onto the executor that the shell is
+    /// running on.
+    pub fn spawner(&self) -> &Spawner {
+        &self.spawner
+    }
+
+    /// Parses and runs a line of input, reporting any errors.
+    pub fn execute(&mut self, line: &str) {
+        let words = match args::split(line) {
+            Ok(words) => words,
+            Err(err) => {
+                println!("error: {}", err);
+                return;
+            },
+        };
+        let (name, args) = match words.split_first() {
+            Some((name, args)) => (name, args),
+            None => return,
+        };
+
+        let command = match command(name) {
+            Some(command) => command,
+            None => {
+                println!("unknown command: {} (try `help`)", name);
+                return;
+            },
+        };
+
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        match (command.run)(self, &args) {
+            Ok(()) => {},
+            Err(CommandError::Usage) =>
+                println!("usage: {} {}", command.name, command.usage),
+            Err(err) => println!("{}: {}", command.name, err),
+        }
+    }
+}
+
+
+#[test_case]
+fn test_register() {
+    fn run(_: &Shell, _: &[&str]) -> Result<(), CommandError> {
+        Ok(())
+    }
+
+    let test = Command {
+        name: "test-register",
+        usage: "",
+        summary: "does nothing",
+        run,
+    };
+    assert_eq!(register(test), Ok(()));
+    assert_eq!(register(test), Err(RegisterError::AlreadyRegistered));
+    assert!(command("test-register").is_some());
+    assert!(command("help").is_some());
+
+    let names: Vec<_> = commands().iter().map(|c| c.name).collect();
+    assert!(names.windows(2).all(|pair| pair[0] < pair[1]));
+}
@@ -0,0 +1,189 @@
+//! Locks that are safe to share with interrupt handlers.
+//!
+//! For locks held across an `.await`, see `task::sync` instead.
+
+use core::ops::{Deref, DerefMut};
+#[cfg(debug_assertions)]
+use core::panic::Location;
+#[cfg(debug_assertions)]
+use core::sync::atomic::{AtomicPtr, Ordering};
+
+use x86_64::instructions::interrupts;
+
+/// The number of failed attempts to take a lock after which it's reported as
+/// a possible deadlock. Only used in debug builds.
+#[cfg(debug_assertions)]
+const DEADLOCK_SPINS: usize = 10_000_000;
+
+/// A spinlock that disables interrupts while it's held.
+///
+/// Because an interrupt can't fire while the lock is held, an interrupt handler
+/// can never spin on a lock that the code it interrupted is holding, which
+/// would otherwise deadlock. Interrupts are restored to their previous state
+/// when the guard is dropped.
+///
+/// In debug builds, a lock that can't be taken after `DEADLOCK_SPINS`
+/// attempts is reported over COM1, along with where it was taken.
+pub struct IrqSafeMutex<T: ?Sized> {
+    /// Where the lock was last taken.
+    #[cfg(debug_assertions)]
+    owner: AtomicPtr<Location<'static>>,
+    inner: spin::Mutex<T>,
+}
+
+impl<T> IrqSafeMutex<T> {
+    pub const fn new(value: T) -> Self {
+        IrqSafeMutex {
+            #[cfg(debug_assertions)]
+            owner: AtomicPtr::new(core::ptr::null_mut()),
+            inner: spin::Mutex::new(value),
+        }
+    }
+}
+
+impl<T: ?Sized> IrqSafeMutex<T> {
+    /// Disables interrupts, then spins until the lock is free.
+    #[track_caller]
+    pub fn lock(&self) -> IrqSafeMutexGuard<T> {
+        let restore_interrupts = interrupts::are_enabled();
+        interrupts::disable();
+
+        #[cfg(debug_assertions)]
+        let mut spins = 0;
+
+        loop {
+            if let Some(guard) = self.inner.try_lock() {
+                #[cfg(debug_assertions)]
+                self.set_owner();
+
+                return IrqSafeMutexGuard {
+                    inner: core::mem::ManuallyDrop::new(guard),
+                    restore_interrupts,
+                };
+            }
+
+            #[cfg(debug_assertions)]
+            {
+                spins += 1;
+                if spins == DEADLOCK_SPINS {
+                    self.report_deadlock();
+                }
+            }
+            core::hint::spin_loop();
+        }
+    }
+
+    /// Takes the lock if it's free right now.
+    #[track_caller]
+    pub fn try_lock(&self) -> Option<IrqSafeMutexGuard<T>> {
+        let restore_interrupts = interrupts::are_enabled();
+        interrupts::disable();
+
+        match self.inner.try_lock() {
+            Some(guard) => {
+                #[cfg(debug_assertions)]
+                self.set_owner();
+
+                Some(IrqSafeMutexGuard {
+                    inner: core::mem::ManuallyDrop::new(guard),
+                    restore_interrupts,
+                })
+            },
+            None => {
+                if restore_interrupts {
+                    interrupts::enable();
+                }
+                None
+            },
+        }
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.inner.is_locked()
+    }
+
+    #[cfg(debug_assertions)]
+    #[track_caller]
+    fn set_owner(&self) {
+        let location = Location::caller() as *const _ as *mut _;
+        self.owner.store(location, Ordering::Relaxed);
+    }
+
+    /// Reports a lock that seems to be stuck.
+    ///
+    /// Writes straight to COM1 rather than going through `serial_println!`, as
+    /// the stuck lock might be the one guarding the serial port.
+    #[cfg(debug_assertions)]
+    #[track_caller]
+    fn report_deadlock(&self) {
+        use core::fmt::Write;
+
+        let mut port =
+            unsafe { crate::serial::uart::Uart::new(crate::serial::COM1) };
+        let _ = write!(
+            port,
+            "WARNING: possible deadlock on {} at {}",
+            core::any::type_name::<T>(),
+            Location::caller(),
+        );
+
+        let owner = self.owner.load(Ordering::Relaxed);
+        let _ = match unsafe { owner.as_ref() } {
+            Some(owner) => writeln!(port, "; lock held since {}", owner),
+            None => writeln!(port),
+        };
+    }
+}
+
+/// Releases the lock, then restores interrupts, when dropped.
+pub struct IrqSafeMutexGuard<'a, T: ?Sized> {
+    inner:              core::mem::ManuallyDrop<spin::MutexGuard<'a, T>>,
+    restore_interrupts: bool,
+}
+
+impl<T: ?Sized> Deref for IrqSafeMutexGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.inner
+    }
+}
+
+impl<T: ?Sized> DerefMut for IrqSafeMutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+}
+
+impl<T: ?Sized> Drop for IrqSafeMutexGuard<'_, T> {
+    fn drop(&mut self) {
+        // The lock has to be released first, or an interrupt could arrive
+        // while it's still held.
+        unsafe { core::mem::ManuallyDrop::drop(&mut self.inner) };
+        if self.restore_interrupts {
+            interrupts::enable();
+        }
+    }
+}
+
+
+#[test_case]
+fn test_irq_safe_mutex() {
+    let mutex = IrqSafeMutex::new(0);
+    assert!(interrupts::are_enabled());
+
+    {
+        let mut guard = mutex.lock();
+        *guard += 1;
+        assert!(!interrupts::are_enabled());
+
+        // a nested lock mustn't re-enable interrupts when it's released
+        let other = IrqSafeMutex::new(());
+        drop(other.lock());
+        assert!(!interrupts::are_enabled());
+        assert!(mutex.try_lock().is_none());
+    }
+
+    assert!(interrupts::are_enabled());
+    assert_eq!(*mutex.lock(), 1);
+}
@@ -1,27 +1,132 @@
-use alloc::collections::BTreeMap;
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::rc::Rc;
 use alloc::sync::Arc;
 use alloc::task::Wake;
+use alloc::vec::Vec;
+use core::cell::{Cell, RefCell};
+use core::fmt;
 use core::future::Future;
+use core::sync::atomic::{AtomicBool, Ordering};
 use core::task::{Context, Poll, Waker};
 
 use crossbeam_queue::ArrayQueue;
 
-use super::{Task, TaskId};
+use super::join::{self, JoinHandle};
+use super::{Priority, Task, TaskId, TaskStats};
+
+/// The default maximum number of polls performed per call to
+/// `run_ready_tasks`.
+const DEFAULT_POLL_BUDGET: usize = 64;
+
+/// The reason a task couldn't be spawned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpawnError {
+    /// The executor is already running as many tasks as it was created for.
+    TooManyTasks,
+}
+
+impl fmt::Display for SpawnError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SpawnError::TooManyTasks => write!(f, "too many tasks"),
+        }
+    }
+}
+
+/// One ready queue per priority, indexed by `Priority::as_usize`.
+///
+/// Each task is queued at most once at a time (see `TaskWaker::queued`), and
+/// the executor never holds more tasks than the queues have room for, so a
+/// push should never fail. If one does anyway, the `overflowed` flag is raised
+/// instead of panicking, and the executor rebuilds the queues from each task's
+/// `queued` flag.
+struct ReadyQueues {
+    queues:     [ArrayQueue<TaskId>; Priority::ALL.len()],
+    overflowed: AtomicBool,
+}
+
+impl ReadyQueues {
+    fn new(max_tasks: usize) -> Self {
+        ReadyQueues {
+            queues:     [
+                ArrayQueue::new(max_tasks),
+                ArrayQueue::new(max_tasks),
+                ArrayQueue::new(max_tasks),
+            ],
+            overflowed: AtomicBool::new(false),
+        }
+    }
+
+    /// Must not block or allocate, as it's called from interrupt handlers.
+    fn push(&self, priority: Priority, task_id: TaskId) {
+        if self.queues[priority.as_usize()].push(task_id).is_err() {
+            self.overflowed.store(true, Ordering::Release);
+        }
+    }
+
+    /// Pops the next task, taking from the highest non-empty priority.
+    fn pop(&self) -> Option<TaskId> {
+        Priority::ALL.iter().find_map(|p| self.queues[p.as_usize()].pop())
+    }
+
+    fn is_empty(&self) -> bool {
+        !self.overflowed.load(Ordering::Acquire)
+            && self.queues.iter().all(|queue| queue.is_empty())
+    }
+
+    fn capacity(&self) -> usize {
+        self.queues[0].capacity()
+    }
+
+    /// Rebuilds the queues after an overflow, from the set of tasks that
+    /// should be in them.
+    fn recover<'a>(&self, wakers: impl Iterator<Item = &'a Arc<TaskWaker>>) {
+        use x86_64::instructions::interrupts::without_interrupts;
+
+        if !self.overflowed.swap(false, Ordering::AcqRel) {
+            return;
+        }
+
+        without_interrupts(|| {
+            while self.pop().is_some() {}
+            for waker in wakers {
+                if waker.queued.load(Ordering::Acquire) {
+                    self.push(waker.priority, waker.task_id);
+                }
+            }
+        });
+    }
+}
 
 struct TaskWaker {
     task_id:    TaskId,
-    task_queue: Arc<ArrayQueue<TaskId>>,
+    priority:   Priority,
+    /// Whether the task is currently in the ready queue, so that repeated
+    /// wakeups don't queue it again.
+    queued:     AtomicBool,
+    task_queue: Arc<ReadyQueues>,
 }
 
 impl TaskWaker {
-    fn gen_waker(
-        task_id: TaskId, task_queue: Arc<ArrayQueue<TaskId>>,
-    ) -> Waker {
-        Waker::from(Arc::new(TaskWaker { task_id, task_queue }))
+    /// Creates the waker for a new task, and queues the task for its first
+    /// poll.
+    fn new(
+        task_id: TaskId, priority: Priority, task_queue: Arc<ReadyQueues>,
+    ) -> Arc<Self> {
+        let waker = Arc::new(TaskWaker {
+            task_id,
+            priority,
+            queued: AtomicBool::new(false),
+            task_queue,
+        });
+        waker.wake_task();
+        waker
     }
 
     fn wake_task(&self) {
-        self.task_queue.push(self.task_id).expect("task_queue full");
+        if !self.queued.swap(true, Ordering::AcqRel) {
+            self.task_queue.push(self.priority, self.task_id);
+        }
     }
 }
 
@@ -35,28 +140,144 @@ impl Wake for TaskWaker {
     }
 }
 
+/// Tasks spawned through a `Spawner`, waiting for the executor to take them.
+type NewTasks = Rc<RefCell<Vec<(Task, Arc<TaskWaker>)>>>;
+
+/// The priority and statistics of each live task, by task.
+type TaskStatsMap = BTreeMap<TaskId, (Priority, Rc<Cell<TaskStats>>)>;
+
+/// A handle for spawning tasks onto an `Executor`, including from inside tasks
+/// that are already running on it.
+#[derive(Clone)]
+pub struct Spawner {
+    new_tasks:  NewTasks,
+    task_count: Rc<Cell<usize>>,
+    /// The statistics of every live task, which are kept up to date by the
+    /// tasks themselves.
+    task_stats: Rc<RefCell<TaskStatsMap>>,
+    queue:      Arc<ReadyQueues>,
+}
+
+impl Spawner {
+    /// Spawns a task with `Priority::Normal`.
+    pub fn spawn<F>(
+        &self, future: F,
+    ) -> Result<JoinHandle<F::Output>, SpawnError>
+    where
+        F: Future + 'static,
+        F::Output: 'static,
+    {
+        self.spawn_with_priority(future, Priority::default())
+    }
+
+    pub fn spawn_with_priority<F>(
+        &self, future: F, priority: Priority,
+    ) -> Result<JoinHandle<F::Output>, SpawnError>
+    where
+        F: Future + 'static,
+        F::Output: 'static,
+    {
+        if self.task_count.get() >= self.queue.capacity() {
+            return Err(SpawnError::TooManyTasks);
+        }
+        self.task_count.set(self.task_count.get() + 1);
+
+        let task_id = TaskId::new();
+        let (future, handle) = join::joinable(task_id, future);
+        let task = Task::new(task_id, future, priority);
+        let waker = TaskWaker::new(task_id, priority, self.queue.clone());
+        self.task_stats
+            .borrow_mut()
+            .insert(task_id, (priority, task.stats.clone()));
+
+        // The executor picks the task up the next time it checks its queue.
+        self.new_tasks.borrow_mut().push((task, waker));
+        Ok(handle)
+    }
+
+    /// Returns the ID, priority and statistics of every live task.
+    ///
+    /// Unlike `Executor::task_stats`, this can be called from inside a task.
+    pub fn task_stats(&self) -> Vec<(TaskId, Priority, TaskStats)> {
+        self.task_stats
+            .borrow()
+            .iter()
+            .map(|(&id, (priority, stats))| (id, *priority, stats.get()))
+            .collect()
+    }
+}
+
 pub struct Executor {
-    tasks:  BTreeMap<TaskId, Task>,
-    wakers: BTreeMap<TaskId, Waker>,
-    queue:  Arc<ArrayQueue<TaskId>>,
+    tasks:       BTreeMap<TaskId, Task>,
+    wakers:      BTreeMap<TaskId, Arc<TaskWaker>>,
+    queue:       Arc<ReadyQueues>,
+    spawner:     Spawner,
+    poll_budget: usize,
 }
 
 impl Executor {
+    /// Creates an executor that can run up to `max_tasks` tasks at once.
     pub fn new(max_tasks: usize) -> Self {
+        let queue = Arc::new(ReadyQueues::new(max_tasks));
         Executor {
-            tasks:  BTreeMap::new(),
+            tasks: BTreeMap::new(),
             wakers: BTreeMap::new(),
-            queue:  Arc::new(ArrayQueue::new(max_tasks)),
+            spawner: Spawner {
+                new_tasks:  Rc::new(RefCell::new(Vec::new())),
+                task_count: Rc::new(Cell::new(0)),
+                task_stats: Rc::new(RefCell::new(BTreeMap::new())),
+                queue:      queue.clone(),
+            },
+            queue,
+            poll_budget: DEFAULT_POLL_BUDGET,
         }
     }
 
-    pub fn spawn(&mut self, future: impl Future<Output = ()> + 'static) {
-        let task = Task::new(future);
-        let task_id = task.id;
-        if self.tasks.insert(task.id, task).is_some() {
-            panic!("task with same ID already in tasks");
-        }
-        self.queue.push(task_id).expect("queue full");
+    /// Returns a handle that can spawn tasks onto this executor.
+    pub fn spawner(&self) -> Spawner {
+        self.spawner.clone()
+    }
+
+    /// Spawns a task with `Priority::Normal`.
+    pub fn spawn<F>(
+        &mut self, future: F,
+    ) -> Result<JoinHandle<F::Output>, SpawnError>
+    where
+        F: Future + 'static,
+        F::Output: 'static,
+    {
+        self.spawner.spawn(future)
+    }
+
+    pub fn spawn_with_priority<F>(
+        &mut self, future: F, priority: Priority,
+    ) -> Result<JoinHandle<F::Output>, SpawnError>
+    where
+        F: Future + 'static,
+        F::Output: 'static,
+    {
+        self.spawner.spawn_with_priority(future, priority)
+    }
+
+    /// Sets the maximum number of polls performed before the executor checks
+    /// whether it can sleep. Must be at least 1.
+    pub fn set_poll_budget(&mut self, budget: usize) {
+        assert!(budget > 0, "poll budget must be at least 1");
+        self.poll_budget = budget;
+    }
+
+    /// Returns the number of tasks that haven't finished yet.
+    pub fn task_count(&self) -> usize {
+        self.spawner.task_count.get()
+    }
+
+    /// Returns the ID, priority and statistics of every live task.
+    pub fn task_stats(
+        &self,
+    ) -> impl Iterator<Item = (TaskId, Priority, TaskStats)> + '_ {
+        self.tasks
+            .values()
+            .map(|task| (task.id, task.priority, task.stats.get()))
     }
 
     pub fn run(&mut self) -> ! {
@@ -66,6 +287,14 @@ impl Executor {
         }
     }
 
+    /// Runs tasks until none of them are ready.
+    #[cfg(test)]
+    pub(crate) fn run_until_idle(&mut self) {
+        while !self.queue.is_empty() {
+            self.run_ready_tasks();
+        }
+    }
+
     fn sleep_if_idle(&self) {
         use x86_64::instructions::interrupts;
 
@@ -78,25 +307,182 @@ impl Executor {
         }
     }
 
+    /// Polls ready tasks, highest priority first.
+    ///
+    /// Each task is polled at most once per call, so a task that wakes itself
+    /// straight away has to wait for the next round rather than starving
+    /// everything below it. At most `poll_budget` polls are performed.
     fn run_ready_tasks(&mut self) {
-        let Self { tasks, wakers, queue } = self;
+        let Self { tasks, wakers, queue, spawner, poll_budget } = self;
+
+        let mut polled = BTreeSet::new();
+        let mut deferred = Vec::new();
+
+        while polled.len() < *poll_budget {
+            // adopt anything spawned since the last poll
+            for (task, waker) in spawner.new_tasks.borrow_mut().drain(..) {
+                wakers.insert(task.id, waker);
+                if tasks.insert(task.id, task).is_some() {
+                    panic!("task with same ID already in tasks");
+                }
+            }
+            queue.recover(wakers.values());
 
-        while let Some(task_id) = queue.pop() {
+            let task_id = match queue.pop() {
+                Some(task_id) => task_id,
+                None => break,
+            };
             let task = match tasks.get_mut(&task_id) {
                 Some(task) => task,
                 None => continue, // task no longer exists
             };
-            let waker = wakers.entry(task_id).or_insert_with(|| {
-                TaskWaker::gen_waker(task_id, queue.clone())
-            });
-            let mut context = Context::from_waker(waker);
+            if !polled.insert(task_id) {
+                // already polled this round, so it's queued again afterwards,
+                // unless something else queues it first
+                wakers[&task_id].queued.store(false, Ordering::Release);
+                deferred.push(task_id);
+                continue;
+            }
+
+            let task_waker = &wakers[&task_id];
+            task_waker.queued.store(false, Ordering::Release);
+            let waker = Waker::from(task_waker.clone());
+            let mut context = Context::from_waker(&waker);
             match task.poll(&mut context) {
                 Poll::Pending => {},
                 Poll::Ready(()) => {
                     tasks.remove(&task_id);
                     wakers.remove(&task_id);
+                    spawner.task_stats.borrow_mut().remove(&task_id);
+                    spawner.task_count.set(spawner.task_count.get() - 1);
                 },
             }
         }
+
+        for task_id in deferred {
+            wakers[&task_id].wake_task();
+        }
     }
 }
+
+#[test_case]
+fn test_priority_order() {
+    let order = Rc::new(RefCell::new(Vec::new()));
+    let mut executor = Executor::new(10);
+
+    for &priority in &[Priority::Low, Priority::Normal, Priority::High] {
+        let order = order.clone();
+        executor
+            .spawn_with_priority(
+                async move { order.borrow_mut().push(priority) },
+                priority,
+            )
+            .unwrap();
+    }
+    executor.run_ready_tasks();
+
+    assert_eq!(*order.borrow(), [
+        Priority::High,
+        Priority::Normal,
+        Priority::Low
+    ]);
+    assert_eq!(executor.task_count(), 0);
+}
+
+#[test_case]
+fn test_poll_budget() {
+    use core::pin::Pin;
+
+    /// Wakes itself every time it's polled, and never finishes.
+    struct Spin;
+    impl Future for Spin {
+        type Output = ();
+
+        fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    }
+
+    let mut executor = Executor::new(10);
+    executor.spawn_with_priority(Spin, Priority::High).unwrap();
+    executor.spawn(async {}).unwrap();
+    executor.run_ready_tasks();
+
+    // the spinning task was polled once, and didn't starve the other task
+    let (_, _, stats) = executor.task_stats().next().unwrap();
+    assert_eq!(stats.polls, 1);
+    assert_eq!(executor.task_count(), 1);
+
+    // with more ready tasks than the budget, the rest wait for the next round
+    let mut executor = Executor::new(10);
+    executor.set_poll_budget(2);
+    for _ in 0..3 {
+        executor.spawn(async {}).unwrap();
+    }
+    executor.run_ready_tasks();
+    assert_eq!(executor.task_count(), 1);
+    executor.run_ready_tasks();
+    assert_eq!(executor.task_count(), 0);
+
+    // a deferred task that the queues are rebuilt with before the round ends
+    // is still only queued once
+    let mut executor = Executor::new(10);
+    let spin = executor.spawn_with_priority(Spin, Priority::High).unwrap();
+    let queue = executor.queue.clone();
+    executor
+        .spawn(async move { queue.overflowed.store(true, Ordering::Release) })
+        .unwrap();
+    executor.run_ready_tasks();
+    assert_eq!(executor.queue.pop(), Some(spin.id()));
+    assert_eq!(executor.queue.pop(), None);
+}
+
+#[test_case]
+fn test_join_and_abort() {
+    use core::future::pending;
+    use core::pin::Pin;
+
+    use super::JoinError;
+
+    fn poll_now<T>(handle: &mut JoinHandle<T>) -> Poll<Result<T, JoinError>> {
+        let waker = futures_util::task::noop_waker();
+        Pin::new(handle).poll(&mut Context::from_waker(&waker))
+    }
+
+    let mut executor = Executor::new(10);
+    let spawner = executor.spawner();
+
+    let finished = executor.spawn(async move {
+        // spawn a task from inside a task, and await its output
+        spawner.spawn(async { 6 * 7 }).unwrap().await
+    });
+    let mut finished = finished.unwrap();
+    let mut aborted = executor.spawn(pending::<()>()).unwrap();
+    executor.run_ready_tasks();
+    aborted.abort();
+    executor.run_ready_tasks();
+
+    assert_eq!(poll_now(&mut finished), Poll::Ready(Ok(Ok(42))));
+    assert_eq!(poll_now(&mut aborted), Poll::Ready(Err(JoinError::Cancelled)));
+    assert_eq!(executor.task_count(), 0);
+}
+
+#[test_case]
+fn test_wakeups_and_spawn_limit() {
+    let mut executor = Executor::new(2);
+    let handle = executor.spawn(core::future::pending::<()>()).unwrap();
+    executor.run_ready_tasks();
+
+    // waking a task many more times than the queue can hold only queues it
+    // once
+    let waker = Waker::from(executor.wakers[&handle.id()].clone());
+    for _ in 0..10 {
+        waker.wake_by_ref();
+    }
+    assert_eq!(executor.queue.pop(), Some(handle.id()));
+    assert_eq!(executor.queue.pop(), None);
+
+    executor.spawn(async {}).unwrap();
+    assert_eq!(executor.spawn(async {}).err(), Some(SpawnError::TooManyTasks));
+}
@@ -0,0 +1,298 @@
+//! Routes keyboard and mouse events to the tasks that want them.
+//!
+//! The scancode and mouse queues can each only be read by one stream, so
+//! `dispatch` reads both and hands each event to every interested
+//! `Subscription`. Key events can be limited to whichever subscription has
+//! focus, so that only one task at a time receives typed text.
+//!
+//! Each subscription belongs to one of the virtual consoles, and each console
+//! has its own focus. Focused key events go to the focus of whichever console
+//! is being shown, so typing on a console only reaches the task reading it.
+
+use alloc::vec::Vec;
+use core::pin::Pin;
+use core::sync::atomic::{AtomicU64, Ordering};
+use core::task::{Context, Poll};
+
+use futures_util::stream::{self, Stream, StreamExt};
+use spin::Mutex;
+
+use super::keyboard::{KeyEvent, KeyEvents};
+use super::mouse::{MouseEvent, MouseStream};
+use super::sync::mpsc;
+use crate::vga::{self, CONSOLE_COUNT};
+
+/// The number of events a subscription can fall behind by before new ones are
+/// dropped.
+const SUBSCRIPTION_CAPACITY: usize = 64;
+
+static BUS: Mutex<Bus> = Mutex::new(Bus::new());
+
+/// Reads keyboard and mouse input and passes it on to subscriptions. Only one
+/// of these should ever be running.
+pub async fn dispatch() {
+    let keys = KeyEvents::new().map(InputEvent::Key);
+    let mouse = MouseStream::new().map(InputEvent::Mouse);
+    let mut events = stream::select(keys, mouse);
+
+    while let Some(event) = events.next().await {
+        BUS.lock().publish(event);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputEvent {
+    Key(KeyEvent),
+    Mouse(MouseEvent),
+}
+
+/// Which key events a subscription receives.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyRouting {
+    None,
+    /// Only while the subscription has focus.
+    Focused,
+    /// Every key event, whichever subscription has focus.
+    All,
+}
+
+/// The kinds of events a subscription receives.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Interest {
+    pub keys:  KeyRouting,
+    pub mouse: bool,
+}
+
+impl Interest {
+    /// Typed input, for a task that reads text.
+    pub const TEXT: Interest =
+        Interest { keys: KeyRouting::Focused, mouse: false };
+    /// Every key event, e.g. for global hotkeys.
+    pub const ALL_KEYS: Interest =
+        Interest { keys: KeyRouting::All, mouse: false };
+    pub const MOUSE: Interest =
+        Interest { keys: KeyRouting::None, mouse: true };
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SubscriptionId(u64);
+
+impl SubscriptionId {
+    fn new() -> Self {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+        SubscriptionId(NEXT_ID.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+struct Subscriber {
+    id:       SubscriptionId,
+    interest: Interest,
+    console:  usize,
+    sender:   mpsc::Sender<InputEvent>,
+}
+
+struct Bus {
+    /// In the order they subscribed.
+    subscribers: Vec<Subscriber>,
+    /// The subscription with focus on each console.
+    focus:       [Option<SubscriptionId>; CONSOLE_COUNT],
+}
+
+impl Bus {
+    const fn new() -> Self {
+        Bus { subscribers: Vec::new(), focus: [None; CONSOLE_COUNT] }
+    }
+
+    fn publish(&mut self, event: InputEvent) {
+        let focus = self.focus[vga::active_console()];
+        for subscriber in &self.subscribers {
+            let wanted = match (event, subscriber.interest.keys) {
+                (InputEvent::Mouse(_), _) => subscriber.interest.mouse,
+                (InputEvent::Key(_), KeyRouting::All) => true,
+                (InputEvent::Key(_), KeyRouting::Focused) =>
+                    focus == Some(subscriber.id),
+                (InputEvent::Key(_), KeyRouting::None) => false,
+            };
+
+            // a subscriber that isn't keeping up misses out, rather than
+            // holding up everyone else
+            if wanted {
+                let _ = subscriber.sender.try_send(event);
+            }
+        }
+    }
+
+    fn remove(&mut self, id: SubscriptionId) {
+        let console = match self.console_of(id) {
+            Some(console) => console,
+            None => return,
+        };
+        self.subscribers.retain(|subscriber| subscriber.id != id);
+
+        // hand focus back to the newest subscriber on the console that can
+        // take it
+        if self.focus[console] == Some(id) {
+            self.focus[console] = self
+                .subscribers
+                .iter()
+                .rev()
+                .find(|subscriber| {
+                    subscriber.console == console
+                        && subscriber.interest.keys == KeyRouting::Focused
+                })
+                .map(|subscriber| subscriber.id);
+        }
+    }
+
+    fn console_of(&self, id: SubscriptionId) -> Option<usize> {
+        self.subscribers
+            .iter()
+            .find(|subscriber| subscriber.id == id)
+            .map(|subscriber| subscriber.console)
+    }
+}
+
+/// A stream of the input events that a task is interested in.
+///
+/// A subscription that receives focused key events takes focus on its console
+/// when it's created if nothing else has it, and gives it up when it's
+/// dropped.
+pub struct Subscription {
+    id:       SubscriptionId,
+    console:  usize,
+    receiver: mpsc::Receiver<InputEvent>,
+}
+
+impl Subscription {
+    /// Subscribes on the first console.
+    pub fn new(interest: Interest) -> Self {
+        Self::on_console(interest, 0)
+    }
+
+    /// Subscribes on the given console, which decides when the subscription
+    /// can receive focused key events.
+    ///
+    /// Panics if `console` isn't less than `CONSOLE_COUNT`.
+    pub fn on_console(interest: Interest, console: usize) -> Self {
+        assert!(console < CONSOLE_COUNT, "no such console: {}", console);
+        let id = SubscriptionId::new();
+        let (sender, receiver) = mpsc::channel(SUBSCRIPTION_CAPACITY);
+
+        let mut bus = BUS.lock();
+        bus.subscribers.push(Subscriber { id, interest, console, sender });
+        if interest.keys == KeyRouting::Focused && bus.focus[console].is_none()
+        {
+            bus.focus[console] = Some(id);
+        }
+
+        Subscription { id, console, receiver }
+    }
+
+    pub fn id(&self) -> SubscriptionId {
+        self.id
+    }
+
+    pub fn console(&self) -> usize {
+        self.console
+    }
+
+    /// Takes focus on its console, so that this subscription receives focused
+    /// key events instead of whichever one had it before.
+    pub fn focus(&self) {
+        BUS.lock().focus[self.console] = Some(self.id);
+    }
+
+    pub fn has_focus(&self) -> bool {
+        BUS.lock().focus[self.console] == Some(self.id)
+    }
+
+    /// Waits for the next key event, skipping any other events.
+    pub async fn next_key(&mut self) -> KeyEvent {
+        loop {
+            if let Some(InputEvent::Key(event)) = self.next().await {
+                return event;
+            }
+        }
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        BUS.lock().remove(self.id);
+    }
+}
+
+impl Stream for Subscription {
+    type Item = InputEvent;
+
+    fn poll_next(
+        mut self: Pin<&mut Self>, cx: &mut Context,
+    ) -> Poll<Option<InputEvent>> {
+        self.receiver.poll_recv(cx)
+    }
+}
+
+/// Returns the subscription that has focus on the console being shown, if
+/// any.
+pub fn focused() -> Option<SubscriptionId> {
+    BUS.lock().focus[vga::active_console()]
+}
+
+/// Gives focus on its console to the given subscription, if it still exists.
+pub fn set_focus(id: SubscriptionId) {
+    let mut bus = BUS.lock();
+    if let Some(console) = bus.console_of(id) {
+        bus.focus[console] = Some(id);
+    }
+}
+
+
+#[test_case]
+fn test_focus_routing() {
+    use super::keyboard::{KeyCode, KeyState, Modifiers};
+    use super::mouse::MouseButtons;
+
+    let key = InputEvent::Key(KeyEvent {
+        code:      KeyCode::A,
+        state:     KeyState::Down,
+        modifiers: Modifiers::new(),
+        key:       None,
+    });
+    let mouse = InputEvent::Mouse(MouseEvent {
+        dx:      1,
+        dy:      0,
+        dz:      0,
+        buttons: MouseButtons::default(),
+    });
+
+    let mut first = Subscription::new(Interest::TEXT);
+    let mut second = Subscription::new(Interest::TEXT);
+    let mut hotkeys = Subscription::new(Interest::ALL_KEYS);
+    let mut pointer = Subscription::new(Interest::MOUSE);
+    assert!(first.has_focus());
+
+    second.focus();
+    BUS.lock().publish(key);
+    BUS.lock().publish(mouse);
+    assert!(first.receiver.try_recv().is_err());
+    assert_eq!(second.receiver.try_recv(), Ok(key));
+    assert_eq!(hotkeys.receiver.try_recv(), Ok(key));
+    assert_eq!(pointer.receiver.try_recv(), Ok(mouse));
+
+    // focus goes back to the first subscription once the second is gone
+    drop(second);
+    BUS.lock().publish(key);
+    assert_eq!(first.receiver.try_recv(), Ok(key));
+
+    // another console has a focus of its own, which only gets keys while the
+    // console is shown
+    let mut other = Subscription::on_console(Interest::TEXT, 1);
+    assert!(other.has_focus() && first.has_focus());
+    BUS.lock().publish(key);
+    assert!(other.receiver.try_recv().is_err());
+    vga::switch_console(1);
+    BUS.lock().publish(key);
+    vga::switch_console(0);
+    assert_eq!(other.receiver.try_recv(), Ok(key));
+    assert!(first.receiver.try_recv().is_err());
+}
@@ -0,0 +1,162 @@
+use alloc::sync::Arc;
+use core::fmt;
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll, Waker};
+
+use spin::Mutex;
+
+use super::TaskId;
+
+/// The reason a task didn't produce an output.
+///
+/// Note that the kernel is built with `panic = "abort"`, so a panicking task
+/// takes the whole kernel down with it rather than being reported here.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JoinError {
+    /// The task was aborted through its `JoinHandle`, or dropped by its
+    /// executor before it could finish.
+    Cancelled,
+}
+
+impl fmt::Display for JoinError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            JoinError::Cancelled => write!(f, "task was cancelled"),
+        }
+    }
+}
+
+/// State shared between a running task and its `JoinHandle`.
+struct JoinState<T> {
+    output:     Option<Result<T, JoinError>>,
+    finished:   bool,
+    aborted:    bool,
+    /// Wakes whoever is awaiting the `JoinHandle`.
+    join_waker: Option<Waker>,
+    /// Wakes the task itself, so that it notices being aborted.
+    task_waker: Option<Waker>,
+}
+
+impl<T> JoinState<T> {
+    fn finish(&mut self, output: Result<T, JoinError>) {
+        self.output = Some(output);
+        self.finished = true;
+        if let Some(waker) = self.join_waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+/// An owned permission to await or abort a spawned task.
+///
+/// Dropping a `JoinHandle` detaches the task; it keeps running, but its output
+/// is discarded.
+pub struct JoinHandle<T> {
+    id:    TaskId,
+    state: Arc<Mutex<JoinState<T>>>,
+}
+
+impl<T> JoinHandle<T> {
+    pub fn id(&self) -> TaskId {
+        self.id
+    }
+
+    /// Requests that the task stops. The task is dropped the next time the
+    /// executor gets to it, and the handle then resolves to
+    /// `Err(JoinError::Cancelled)`.
+    ///
+    /// Does nothing if the task has already finished.
+    pub fn abort(&self) {
+        let mut state = self.state.lock();
+        if !state.finished {
+            state.aborted = true;
+            if let Some(waker) = state.task_waker.take() {
+                waker.wake();
+            }
+        }
+    }
+
+    /// Returns true if the task has finished, been cancelled, or been dropped.
+    pub fn is_finished(&self) -> bool {
+        self.state.lock().finished
+    }
+}
+
+impl<T> Future for JoinHandle<T> {
+    type Output = Result<T, JoinError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let mut state = self.state.lock();
+        match state.output.take() {
+            Some(output) => Poll::Ready(output),
+            None if state.finished =>
+                panic!("JoinHandle polled after completion"),
+            None => {
+                state.join_waker = Some(cx.waker().clone());
+                Poll::Pending
+            },
+        }
+    }
+}
+
+/// Wraps a future so that its output ends up in a `JoinHandle`.
+struct Joinable<F: Future> {
+    future: F,
+    state:  Arc<Mutex<JoinState<F::Output>>>,
+}
+
+impl<F: Future> Future for Joinable<F> {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        // Safe because `future` is never moved out of `self`, including in
+        // `Drop`.
+        let this = unsafe { self.get_unchecked_mut() };
+        let future = unsafe { Pin::new_unchecked(&mut this.future) };
+
+        {
+            let mut state = this.state.lock();
+            if state.aborted {
+                state.finish(Err(JoinError::Cancelled));
+                return Poll::Ready(());
+            }
+            state.task_waker = Some(cx.waker().clone());
+        }
+
+        match future.poll(cx) {
+            Poll::Ready(output) => {
+                this.state.lock().finish(Ok(output));
+                Poll::Ready(())
+            },
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl<F: Future> Drop for Joinable<F> {
+    fn drop(&mut self) {
+        let mut state = self.state.lock();
+        if !state.finished {
+            state.finish(Err(JoinError::Cancelled));
+        }
+    }
+}
+
+/// Splits `future` into a task body and a handle for it.
+pub(super) fn joinable<F>(
+    id: TaskId, future: F,
+) -> (impl Future<Output = ()>, JoinHandle<F::Output>)
+where
+    F: Future,
+{
+    let state = Arc::new(Mutex::new(JoinState {
+        output:     None,
+        finished:   false,
+        aborted:    false,
+        join_waker: None,
+        task_waker: None,
+    }));
+    let handle = JoinHandle { id, state: state.clone() };
+    (Joinable { future, state }, handle)
+}
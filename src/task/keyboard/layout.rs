@@ -0,0 +1,196 @@
+use alloc::boxed::Box;
+use core::sync::atomic::{AtomicU8, Ordering};
+
+use pc_keyboard::{
+    layouts, DecodedKey, HandleControl, Keyboard, KeyboardLayout, ScancodeSet,
+    ScancodeSet1, ScancodeSet2,
+};
+
+static LAYOUT: AtomicU8 = AtomicU8::new(Layout::Us104 as u8);
+static SCANCODE_SET: AtomicU8 = AtomicU8::new(ScancodeSetKind::Set1 as u8);
+
+/// A keyboard layout that scancodes can be decoded with.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Layout {
+    Us104     = 0,
+    Uk105     = 1,
+    De105     = 2,
+    Dvorak104 = 3,
+}
+
+impl Layout {
+    pub const ALL: [Layout; 4] =
+        [Layout::Us104, Layout::Uk105, Layout::De105, Layout::Dvorak104];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Layout::Us104 => "US (104-key)",
+            Layout::Uk105 => "UK (105-key)",
+            Layout::De105 => "German (105-key)",
+            Layout::Dvorak104 => "Dvorak (104-key)",
+        }
+    }
+
+    /// Returns the layout after this one in `Layout::ALL`, wrapping around.
+    pub fn next(self) -> Layout {
+        Self::ALL[(self as usize + 1) % Self::ALL.len()]
+    }
+
+    fn from_u8(value: u8) -> Layout {
+        Self::ALL[value as usize]
+    }
+}
+
+/// The scancode set that the keyboard controller delivers.
+///
+/// Note that this only changes how scancodes are decoded; the controller
+/// translates to set 1 by default, and has to be told separately if it should
+/// send anything else.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScancodeSetKind {
+    Set1 = 1,
+    Set2 = 2,
+}
+
+impl ScancodeSetKind {
+    /// Returns the other scancode set.
+    pub fn next(self) -> ScancodeSetKind {
+        match self {
+            ScancodeSetKind::Set1 => ScancodeSetKind::Set2,
+            ScancodeSetKind::Set2 => ScancodeSetKind::Set1,
+        }
+    }
+}
+
+/// Returns the layout that keyboard input is currently decoded with.
+pub fn layout() -> Layout {
+    Layout::from_u8(LAYOUT.load(Ordering::Relaxed))
+}
+
+/// Changes the layout that keyboard input is decoded with. Takes effect from
+/// the next key event.
+pub fn set_layout(layout: Layout) {
+    LAYOUT.store(layout as u8, Ordering::Relaxed);
+}
+
+pub fn scancode_set() -> ScancodeSetKind {
+    match SCANCODE_SET.load(Ordering::Relaxed) {
+        2 => ScancodeSetKind::Set2,
+        _ => ScancodeSetKind::Set1,
+    }
+}
+
+/// Changes the scancode set that keyboard input is decoded with. Takes effect
+/// from the next scancode.
+pub fn set_scancode_set(set: ScancodeSetKind) {
+    SCANCODE_SET.store(set as u8, Ordering::Relaxed);
+}
+
+/// The parts of `pc_keyboard::Keyboard` that `KeyDecoder` needs, without its
+/// type parameters, so that it can be swapped out at runtime.
+pub(super) trait Decode: Send {
+    fn add_byte(
+        &mut self, byte: u8,
+    ) -> Result<Option<pc_keyboard::KeyEvent>, pc_keyboard::Error>;
+
+    fn process_keyevent(
+        &mut self, event: pc_keyboard::KeyEvent,
+    ) -> Option<DecodedKey>;
+}
+
+impl<L, S> Decode for Keyboard<L, S>
+where
+    L: KeyboardLayout + Send,
+    S: ScancodeSet + Send,
+{
+    fn add_byte(
+        &mut self, byte: u8,
+    ) -> Result<Option<pc_keyboard::KeyEvent>, pc_keyboard::Error> {
+        Keyboard::add_byte(self, byte)
+    }
+
+    fn process_keyevent(
+        &mut self, event: pc_keyboard::KeyEvent,
+    ) -> Option<DecodedKey> {
+        Keyboard::process_keyevent(self, event)
+    }
+}
+
+/// Creates a keyboard decoder for the given layout and scancode set.
+pub(super) fn new_keyboard(
+    layout: Layout, set: ScancodeSetKind,
+) -> Box<dyn Decode> {
+    fn with_set<S>(layout: Layout, set: S) -> Box<dyn Decode>
+    where
+        S: ScancodeSet + Send + 'static,
+    {
+        let ctrl = HandleControl::MapLettersToUnicode;
+        match layout {
+            Layout::Us104 =>
+                Box::new(Keyboard::new(layouts::Us104Key, set, ctrl)),
+            Layout::Uk105 =>
+                Box::new(Keyboard::new(layouts::Uk105Key, set, ctrl)),
+            Layout::De105 =>
+                Box::new(Keyboard::new(layouts::De105Key, set, ctrl)),
+            Layout::Dvorak104 =>
+                Box::new(Keyboard::new(layouts::Dvorak104Key, set, ctrl)),
+        }
+    }
+
+    match set {
+        ScancodeSetKind::Set1 => with_set(layout, ScancodeSet1),
+        ScancodeSetKind::Set2 => with_set(layout, ScancodeSet2),
+    }
+}
+
+
+#[test_case]
+fn test_layouts() {
+    use pc_keyboard::KeyState;
+    use ScancodeSetKind::{Set1, Set2};
+
+    use super::KeyDecoder;
+
+    /// Decodes a key press, given as its scancodes.
+    fn decode(
+        layout: Layout, set: ScancodeSetKind, scancodes: &[u8],
+    ) -> Option<DecodedKey> {
+        let mut keyboard = new_keyboard(layout, set);
+        let mut key = None;
+        for &scancode in scancodes {
+            if let Ok(Some(event)) = keyboard.add_byte(scancode) {
+                key = keyboard.process_keyevent(event);
+            }
+        }
+        key
+    }
+
+    let unicode = |c| Some(DecodedKey::Unicode(c));
+    assert_eq!(decode(Layout::Us104, Set1, &[0x2c]), unicode('z'));
+    assert_eq!(decode(Layout::De105, Set1, &[0x2c]), unicode('y'));
+    assert_eq!(decode(Layout::De105, Set1, &[0x15]), unicode('z'));
+    assert_eq!(decode(Layout::De105, Set2, &[0x1a]), unicode('y'));
+    assert_eq!(decode(Layout::Dvorak104, Set1, &[0x10]), unicode('\''));
+    assert_eq!(decode(Layout::Dvorak104, Set2, &[0x15]), unicode('\''));
+    assert_eq!(decode(Layout::Uk105, Set1, &[0x2b]), unicode('#'));
+    assert_eq!(decode(Layout::Uk105, Set1, &[0x2a, 0x04]), unicode('£'));
+
+    // shift stays held across a change of layout
+    let previous = (layout(), scancode_set());
+    set_layout(Layout::Us104);
+    set_scancode_set(Set1);
+    let mut decoder = KeyDecoder::new();
+    assert_eq!(
+        decoder.add_byte(0x2a).map(|event| event.state),
+        Some(KeyState::Down)
+    );
+    set_layout(Layout::De105);
+    let event = decoder.add_byte(0x2c).unwrap();
+    assert!(event.modifiers.shift());
+    assert_eq!(event.key, unicode('Y'));
+
+    set_layout(previous.0);
+    set_scancode_set(previous.1);
+}
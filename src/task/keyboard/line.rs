@@ -0,0 +1,301 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use super::{DecodedKey, KeyCode, KeyEvent};
+use crate::sync::IrqSafeMutex;
+use crate::task::input::{Interest, Subscription};
+use crate::vga::{self, VGAWriter};
+
+/// The number of lines kept in a `LineEditor`'s history.
+const HISTORY_LENGTH: usize = 32;
+
+/// What a key event did to a `LineEditor`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LineEdit {
+    /// The event didn't change anything.
+    Ignored,
+    /// The line's contents changed.
+    Changed,
+    /// Only the cursor moved.
+    Moved,
+    /// Enter was pressed; holds the finished line.
+    Submitted(String),
+}
+
+/// A line of input being edited, along with the lines submitted before it.
+///
+/// This only deals with the contents of the line; see `LineReader` for one that
+/// reads from the keyboard and draws to the screen.
+pub struct LineEditor {
+    chars:       Vec<char>,
+    cursor:      usize,
+    max_len:     usize,
+    history:     Vec<String>,
+    /// The history entry being shown, if any.
+    history_pos: Option<usize>,
+    /// The line that was being typed before browsing the history.
+    draft:       Vec<char>,
+}
+
+impl LineEditor {
+    /// Creates an editor for lines of up to `max_len` characters.
+    pub fn new(max_len: usize) -> Self {
+        LineEditor {
+            chars: Vec::new(),
+            cursor: 0,
+            max_len,
+            history: Vec::new(),
+            history_pos: None,
+            draft: Vec::new(),
+        }
+    }
+
+    pub fn chars(&self) -> &[char] {
+        &self.chars
+    }
+
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    pub fn set_max_len(&mut self, max_len: usize) {
+        self.max_len = max_len;
+    }
+
+    /// Applies a key event to the line.
+    pub fn handle(&mut self, event: &KeyEvent) -> LineEdit {
+        if !event.is_press() {
+            return LineEdit::Ignored;
+        }
+
+        match event.code {
+            KeyCode::Enter | KeyCode::NumpadEnter => self.submit(),
+            KeyCode::Backspace if self.cursor > 0 => {
+                self.cursor -= 1;
+                self.chars.remove(self.cursor);
+                LineEdit::Changed
+            },
+            KeyCode::Delete if self.cursor < self.chars.len() => {
+                self.chars.remove(self.cursor);
+                LineEdit::Changed
+            },
+            KeyCode::ArrowLeft if self.cursor > 0 => {
+                self.cursor -= 1;
+                LineEdit::Moved
+            },
+            KeyCode::ArrowRight if self.cursor < self.chars.len() => {
+                self.cursor += 1;
+                LineEdit::Moved
+            },
+            KeyCode::Home => {
+                self.cursor = 0;
+                LineEdit::Moved
+            },
+            KeyCode::End => {
+                self.cursor = self.chars.len();
+                LineEdit::Moved
+            },
+            KeyCode::ArrowUp => self.history_prev(),
+            KeyCode::ArrowDown => self.history_next(),
+            KeyCode::Backspace
+            | KeyCode::Delete
+            | KeyCode::ArrowLeft
+            | KeyCode::ArrowRight => LineEdit::Ignored,
+            _ => match event.key {
+                Some(DecodedKey::Unicode(c)) if !c.is_control() =>
+                    self.insert(c),
+                _ => LineEdit::Ignored,
+            },
+        }
+    }
+
+    fn insert(&mut self, c: char) -> LineEdit {
+        if self.chars.len() >= self.max_len {
+            return LineEdit::Ignored;
+        }
+        self.chars.insert(self.cursor, c);
+        self.cursor += 1;
+        LineEdit::Changed
+    }
+
+    fn submit(&mut self) -> LineEdit {
+        let line: String = self.chars.drain(..).collect();
+        self.cursor = 0;
+        self.history_pos = None;
+        self.draft.clear();
+
+        if !line.is_empty() && self.history.last() != Some(&line) {
+            if self.history.len() == HISTORY_LENGTH {
+                self.history.remove(0);
+            }
+            self.history.push(line.clone());
+        }
+        LineEdit::Submitted(line)
+    }
+
+    fn history_prev(&mut self) -> LineEdit {
+        let pos = match self.history_pos {
+            Some(0) => return LineEdit::Ignored,
+            Some(pos) => pos - 1,
+            None if self.history.is_empty() => return LineEdit::Ignored,
+            None => {
+                self.draft = self.chars.clone();
+                self.history.len() - 1
+            },
+        };
+        self.history_pos = Some(pos);
+        self.chars = self.history[pos].chars().take(self.max_len).collect();
+        self.cursor = self.chars.len();
+        LineEdit::Changed
+    }
+
+    fn history_next(&mut self) -> LineEdit {
+        match self.history_pos {
+            None => return LineEdit::Ignored,
+            Some(pos) if pos + 1 < self.history.len() => {
+                self.history_pos = Some(pos + 1);
+                self.chars =
+                    self.history[pos + 1].chars().take(self.max_len).collect();
+            },
+            Some(_) => {
+                self.history_pos = None;
+                self.chars = core::mem::take(&mut self.draft);
+            },
+        }
+        self.cursor = self.chars.len();
+        LineEdit::Changed
+    }
+}
+
+/// Reads lines from the keyboard, echoing them to a VGA console as they're
+/// edited.
+///
+/// Keys are only read while the reader's console is being shown, and its
+/// input subscription has focus there.
+pub struct LineReader {
+    input:  Subscription,
+    editor: LineEditor,
+}
+
+impl LineReader {
+    /// Creates a reader on the first console, which `print!` writes to.
+    pub fn new() -> Self {
+        Self::on_console(0)
+    }
+
+    /// Creates a reader that echoes to the given console.
+    ///
+    /// Panics if `console` isn't less than `CONSOLE_COUNT`.
+    pub fn on_console(console: usize) -> Self {
+        LineReader {
+            input:  Subscription::on_console(Interest::TEXT, console),
+            editor: LineEditor::new(0),
+        }
+    }
+
+    /// Waits for a line to be typed and submitted with Enter.
+    ///
+    /// The line is edited in place from wherever the cursor currently is, and
+    /// can't be longer than the rest of the screen's current row.
+    pub async fn read_line(&mut self) -> String {
+        let start = self.writer().lock().column();
+        self.editor.set_max_len(vga::BUFFER_WIDTH.saturating_sub(start + 1));
+        let mut drawn = 0;
+
+        loop {
+            let event = self.input.next_key().await;
+            match self.editor.handle(&event) {
+                LineEdit::Ignored => {},
+                LineEdit::Changed | LineEdit::Moved => {
+                    self.redraw(start, drawn);
+                    drawn = self.editor.chars().len();
+                },
+                LineEdit::Submitted(line) => {
+                    let mut writer = self.writer().lock();
+                    writer.set_column(start + drawn);
+                    if self.input.console() == 0 {
+                        // the other sinks that `print!` writes to should see
+                        // the end of the line too
+                        drop(writer);
+                        crate::println!();
+                    }
+                    else {
+                        writer.write_str("\n");
+                    }
+                    return line;
+                },
+            }
+        }
+    }
+
+    pub fn input(&self) -> &Subscription {
+        &self.input
+    }
+
+    /// Returns the console that the line is echoed to.
+    fn writer(&self) -> &'static IrqSafeMutex<VGAWriter> {
+        vga::console(self.input.console())
+    }
+
+    /// Redraws the line, erasing any of the `drawn` characters that are no
+    /// longer needed.
+    fn redraw(&self, start: usize, drawn: usize) {
+        let mut writer = self.writer().lock();
+        let chars = self.editor.chars();
+
+        writer.set_column(start);
+        for c in chars {
+            writer.write_str(c.encode_utf8(&mut [0; 4]));
+        }
+        for _ in chars.len()..drawn {
+            writer.write_byte(b' ');
+        }
+        writer.set_column(start + self.editor.cursor());
+    }
+}
+
+impl Default for LineReader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+
+#[test_case]
+fn test_line_editing() {
+    use super::{KeyState, Modifiers};
+
+    fn press(code: KeyCode, key: Option<DecodedKey>) -> KeyEvent {
+        KeyEvent {
+            code,
+            state: KeyState::Down,
+            modifiers: Modifiers::new(),
+            key,
+        }
+    }
+    fn type_str(editor: &mut LineEditor, s: &str) {
+        for c in s.chars() {
+            editor.handle(&press(KeyCode::A, Some(DecodedKey::Unicode(c))));
+        }
+    }
+
+    let mut editor = LineEditor::new(10);
+    type_str(&mut editor, "helo");
+    editor.handle(&press(KeyCode::ArrowLeft, None));
+    type_str(&mut editor, "l");
+    editor.handle(&press(KeyCode::End, None));
+    editor.handle(&press(KeyCode::Backspace, None));
+    type_str(&mut editor, "o world!");
+
+    // the line is capped at 10 characters
+    assert_eq!(
+        editor.handle(&press(KeyCode::Enter, None)),
+        LineEdit::Submitted("hello worl".into())
+    );
+
+    type_str(&mut editor, "draft");
+    editor.handle(&press(KeyCode::ArrowUp, None));
+    assert_eq!(editor.chars().iter().collect::<String>(), "hello worl");
+    editor.handle(&press(KeyCode::ArrowDown, None));
+    assert_eq!(editor.chars().iter().collect::<String>(), "draft");
+}
@@ -0,0 +1,436 @@
+mod layout;
+mod line;
+
+use alloc::boxed::Box;
+use core::pin::Pin;
+use core::sync::atomic::{AtomicU64, Ordering};
+use core::task::{Context, Poll};
+
+use conquer_once::spin::OnceCell;
+use crossbeam_queue::ArrayQueue;
+use futures_util::stream::{Stream, StreamExt};
+use futures_util::task::AtomicWaker;
+pub use pc_keyboard::{DecodedKey, KeyCode, KeyState};
+
+pub use self::layout::{
+    layout, scancode_set, set_layout, set_scancode_set, Layout, ScancodeSetKind,
+};
+pub use self::line::{LineEdit, LineEditor, LineReader};
+use crate::ps2::{self, Leds};
+use crate::{println, vga};
+
+/// A key being pressed or released.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyEvent {
+    pub code:      KeyCode,
+    pub state:     KeyState,
+    /// The modifiers held (or toggled on) after this event was applied.
+    pub modifiers: Modifiers,
+    /// What the key means under the current layout. Only set for key presses
+    /// that map to something.
+    pub key:       Option<DecodedKey>,
+}
+
+impl KeyEvent {
+    pub fn is_press(&self) -> bool {
+        self.state == KeyState::Down
+    }
+}
+
+/// The state of the modifier and lock keys.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Modifiers {
+    pub lshift:      bool,
+    pub rshift:      bool,
+    pub lctrl:       bool,
+    pub rctrl:       bool,
+    pub lalt:        bool,
+    pub ralt:        bool,
+    pub caps_lock:   bool,
+    pub num_lock:    bool,
+    pub scroll_lock: bool,
+}
+
+impl Modifiers {
+    pub const fn new() -> Self {
+        Modifiers {
+            lshift:      false,
+            rshift:      false,
+            lctrl:       false,
+            rctrl:       false,
+            lalt:        false,
+            ralt:        false,
+            caps_lock:   false,
+            num_lock:    true,
+            scroll_lock: false,
+        }
+    }
+
+    pub fn shift(&self) -> bool {
+        self.lshift || self.rshift
+    }
+
+    pub fn ctrl(&self) -> bool {
+        self.lctrl || self.rctrl
+    }
+
+    pub fn alt(&self) -> bool {
+        self.lalt || self.ralt
+    }
+
+    /// Applies a key event, returning true if one of the lock keys was
+    /// toggled.
+    fn update(&mut self, code: KeyCode, state: KeyState) -> bool {
+        let down = state == KeyState::Down;
+        let locks = (self.caps_lock, self.num_lock, self.scroll_lock);
+        match code {
+            KeyCode::ShiftLeft => self.lshift = down,
+            KeyCode::ShiftRight => self.rshift = down,
+            KeyCode::ControlLeft => self.lctrl = down,
+            KeyCode::ControlRight => self.rctrl = down,
+            KeyCode::AltLeft => self.lalt = down,
+            KeyCode::AltRight => self.ralt = down,
+            KeyCode::CapsLock if down => self.caps_lock = !self.caps_lock,
+            KeyCode::NumpadLock if down => self.num_lock = !self.num_lock,
+            KeyCode::ScrollLock if down => self.scroll_lock = !self.scroll_lock,
+            _ => {},
+        }
+        locks != (self.caps_lock, self.num_lock, self.scroll_lock)
+    }
+
+    /// Presses the keys that bring a newly created keyboard to this state, as
+    /// it starts out with nothing held and the lock keys at their defaults.
+    fn replay(&self, keyboard: &mut dyn layout::Decode) {
+        let initial = Modifiers::new();
+        let keys = [
+            (self.lshift, KeyCode::ShiftLeft),
+            (self.rshift, KeyCode::ShiftRight),
+            (self.lctrl, KeyCode::ControlLeft),
+            (self.rctrl, KeyCode::ControlRight),
+            (self.lalt, KeyCode::AltLeft),
+            (self.ralt, KeyCode::AltRight),
+            (self.caps_lock != initial.caps_lock, KeyCode::CapsLock),
+            (self.num_lock != initial.num_lock, KeyCode::NumpadLock),
+        ];
+        for &(pressed, code) in keys.iter() {
+            if pressed {
+                let event = pc_keyboard::KeyEvent::new(code, KeyState::Down);
+                keyboard.process_keyevent(event);
+            }
+        }
+    }
+
+    fn leds(&self) -> Leds {
+        Leds {
+            scroll_lock: self.scroll_lock,
+            num_lock:    self.num_lock,
+            caps_lock:   self.caps_lock,
+        }
+    }
+}
+
+impl Default for Modifiers {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Turns raw scancodes into `KeyEvent`s, using the current `layout()` and
+/// `scancode_set()`.
+///
+/// Pressing Ctrl+Alt+L switches to the next layout, Ctrl+Alt+S switches the
+/// keyboard to the other scancode set, Alt+F1 onwards switch between the
+/// virtual consoles, and Shift+PageUp and Shift+PageDown scroll through the
+/// scrollback of the console being shown. Any other key that types
+/// something takes that console back to live output.
+pub struct KeyDecoder {
+    keyboard:     Box<dyn layout::Decode>,
+    layout:       Layout,
+    scancode_set: ScancodeSetKind,
+    modifiers:    Modifiers,
+}
+
+impl KeyDecoder {
+    pub fn new() -> Self {
+        let (layout, scancode_set) = (layout(), scancode_set());
+        KeyDecoder {
+            keyboard: layout::new_keyboard(layout, scancode_set),
+            layout,
+            scancode_set,
+            modifiers: Modifiers::new(),
+        }
+    }
+
+    /// Feeds in a scancode, returning an event if it completes one.
+    pub fn add_byte(&mut self, scancode: u8) -> Option<KeyEvent> {
+        self.follow_settings();
+
+        let event = self.keyboard.add_byte(scancode).ok()??;
+        let (code, state) = (event.code, event.state);
+
+        // always process the event, so the keyboard can track its modifiers
+        let key = self.keyboard.process_keyevent(event);
+        if self.modifiers.update(code, state) {
+            // the lights are only cosmetic, so failing to set them is fine
+            let _ = ps2::CONTROLLER.lock().set_leds(self.modifiers.leds());
+        }
+
+        let m = self.modifiers;
+        if code == KeyCode::L && state == KeyState::Down && m.ctrl() && m.alt()
+        {
+            let next = self.layout.next();
+            set_layout(next);
+            println!("\n[keyboard layout: {}]", next.name());
+            return None;
+        }
+
+        if code == KeyCode::S && state == KeyState::Down && m.ctrl() && m.alt()
+        {
+            let next = self.scancode_set.next();
+            match ps2::CONTROLLER.lock().set_scancode_set(next) {
+                Ok(()) => println!("\n[scancode set: {}]", next as u8),
+                Err(err) => println!("\n[scancode set unchanged: {:?}]", err),
+            }
+            return None;
+        }
+
+        if state == KeyState::Down && m.alt() {
+            if let Some(index) = console_key(code) {
+                vga::switch_console(index);
+                return None;
+            }
+        }
+
+        let console = vga::console(vga::active_console());
+        if state == KeyState::Down && m.shift() {
+            let rows = vga::BUFFER_HEIGHT / 2;
+            match code {
+                KeyCode::PageUp => {
+                    console.lock().scroll_up(rows);
+                    return None;
+                },
+                KeyCode::PageDown => {
+                    console.lock().scroll_down(rows);
+                    return None;
+                },
+                _ => {},
+            }
+        }
+
+        let key = if state == KeyState::Down { key } else { None };
+        if key.is_some() {
+            console.lock().return_to_live();
+        }
+
+        Some(KeyEvent { code, state, modifiers: self.modifiers, key })
+    }
+
+    /// Swaps out the underlying keyboard if the layout or scancode set have
+    /// been changed, keeping the modifiers that are held.
+    fn follow_settings(&mut self) {
+        let (layout, scancode_set) = (layout(), scancode_set());
+        if (layout, scancode_set) != (self.layout, self.scancode_set) {
+            self.keyboard = layout::new_keyboard(layout, scancode_set);
+            self.modifiers.replay(self.keyboard.as_mut());
+            self.layout = layout;
+            self.scancode_set = scancode_set;
+        }
+    }
+}
+
+/// Returns the index of the console that a function key switches to.
+fn console_key(code: KeyCode) -> Option<usize> {
+    let index = match code {
+        KeyCode::F1 => 0,
+        KeyCode::F2 => 1,
+        KeyCode::F3 => 2,
+        KeyCode::F4 => 3,
+        KeyCode::F5 => 4,
+        KeyCode::F6 => 5,
+        _ => return None,
+    };
+    Some(index).filter(|&index| index < vga::CONSOLE_COUNT)
+}
+
+impl Default for KeyDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A stream of decoded keyboard events.
+pub struct KeyEvents {
+    scancodes: ScancodeStream,
+    decoder:   KeyDecoder,
+}
+
+impl KeyEvents {
+    pub fn new() -> Self {
+        Self::with_queue_size(SCANCODE_QUEUE_SIZE)
+    }
+
+    /// Creates the stream, with room for `queue_size` scancodes to be waiting
+    /// to be decoded.
+    pub fn with_queue_size(queue_size: usize) -> Self {
+        KeyEvents {
+            scancodes: ScancodeStream::with_capacity(queue_size),
+            decoder:   KeyDecoder::new(),
+        }
+    }
+}
+
+impl Default for KeyEvents {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Stream for KeyEvents {
+    type Item = KeyEvent;
+
+    fn poll_next(
+        mut self: Pin<&mut Self>, cx: &mut Context,
+    ) -> Poll<Option<KeyEvent>> {
+        loop {
+            match self.scancodes.poll_next_unpin(cx) {
+                Poll::Ready(Some(scancode)) => {
+                    if let Some(event) = self.decoder.add_byte(scancode) {
+                        return Poll::Ready(Some(event));
+                    }
+                },
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+/// The number of scancodes that can be waiting to be read by default.
+pub const SCANCODE_QUEUE_SIZE: usize = 100;
+
+static SCANCODE_QUEUE: OnceCell<ArrayQueue<u8>> = OnceCell::uninit();
+static WAKER: AtomicWaker = AtomicWaker::new();
+static DROPPED_SCANCODES: AtomicU64 = AtomicU64::new(0);
+
+/// Called by the keyboard interrupt handler
+///
+/// Must not block or allocate. Scancodes that can't be queued are counted in
+/// `dropped_scancodes`, and reported by the `ScancodeStream` later on, as
+/// printing from here would mean taking the VGA lock.
+pub(crate) fn push_scancode(scancode: u8) {
+    let pushed = match SCANCODE_QUEUE.try_get() {
+        Ok(queue) => queue.push(scancode).is_ok(),
+        Err(_) => false,
+    };
+
+    if pushed {
+        WAKER.wake();
+    }
+    else {
+        DROPPED_SCANCODES.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Returns the number of scancodes that have been dropped, either because the
+/// queue was full or because there wasn't one yet.
+pub fn dropped_scancodes() -> u64 {
+    DROPPED_SCANCODES.load(Ordering::Relaxed)
+}
+
+/// A stream of raw scancodes from the keyboard.
+///
+/// Only one of these can ever be created, which `input::dispatch` does (through
+/// `KeyEvents`); other tasks should use an `input::Subscription` instead.
+pub struct ScancodeStream {
+    /// The value of `dropped_scancodes` when it was last reported.
+    reported_dropped: u64,
+}
+
+impl ScancodeStream {
+    pub fn new() -> Self {
+        Self::with_capacity(SCANCODE_QUEUE_SIZE)
+    }
+
+    /// Creates the stream, with room for `capacity` scancodes to be waiting.
+    /// Scancodes from before then are dropped without being reported.
+    pub fn with_capacity(capacity: usize) -> Self {
+        SCANCODE_QUEUE
+            .try_init_once(|| ArrayQueue::new(capacity))
+            .expect("ScancodeStream::new should only be called once");
+        ScancodeStream { reported_dropped: dropped_scancodes() }
+    }
+
+    /// Warns about any scancodes dropped since the last warning.
+    fn report_dropped(&mut self) {
+        let dropped = dropped_scancodes();
+        if dropped != self.reported_dropped {
+            log::warn!(
+                "scancode queue full; dropped {} scancodes",
+                dropped - self.reported_dropped
+            );
+            self.reported_dropped = dropped;
+        }
+    }
+}
+
+impl Default for ScancodeStream {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Stream for ScancodeStream {
+    type Item = u8;
+
+    fn poll_next(
+        mut self: Pin<&mut Self>, cx: &mut Context,
+    ) -> Poll<Option<u8>> {
+        let queue =
+            SCANCODE_QUEUE.try_get().expect("scancode queue not initialized");
+        self.report_dropped();
+
+        if let Some(scancode) = queue.pop() {
+            return Poll::Ready(Some(scancode));
+        }
+
+        WAKER.register(cx.waker());
+        match queue.pop() {
+            Some(scancode) => {
+                WAKER.take();
+                Poll::Ready(Some(scancode))
+            },
+            None => Poll::Pending,
+        }
+    }
+}
+
+
+#[test_case]
+fn test_scancode_queue_overflow() {
+    use futures_util::task::noop_waker;
+
+    const CAPACITY: usize = 8;
+    let mut stream = ScancodeStream::with_capacity(CAPACITY);
+    let waker = noop_waker();
+    let mut cx = Context::from_waker(&waker);
+
+    // flood the queue the way a stuck key or a burst of interrupts would
+    let dropped = dropped_scancodes();
+    for _ in 0..1000 {
+        push_scancode(0x1e);
+    }
+    assert!(dropped_scancodes() >= dropped + 1000 - CAPACITY as u64);
+
+    let mut received = 0;
+    while let Poll::Ready(Some(_)) = stream.poll_next_unpin(&mut cx) {
+        received += 1;
+    }
+    assert!(received >= CAPACITY);
+
+    // the timer interrupt still gets through after the flood
+    let ticks = crate::interrupts::ticks();
+    for _ in 0..3 {
+        x86_64::instructions::hlt();
+    }
+    assert!(crate::interrupts::ticks() > ticks);
+}
@@ -1,30 +1,55 @@
 mod executor;
+pub mod input;
+mod join;
 pub mod keyboard;
+pub mod mouse;
+pub mod sync;
 
 use alloc::boxed::Box;
+use alloc::rc::Rc;
+use core::cell::Cell;
 use core::future::Future;
 use core::pin::Pin;
 use core::task::{Context, Poll};
 
-pub use executor::Executor;
+pub use executor::{Executor, SpawnError, Spawner};
+pub use join::{JoinError, JoinHandle};
 
 struct Task {
-    id:     TaskId,
-    future: Pin<Box<dyn Future<Output = ()>>>,
+    id:       TaskId,
+    priority: Priority,
+    /// Shared with the executor's `Spawner`s, so tasks can see it too.
+    stats:    Rc<Cell<TaskStats>>,
+    future:   Pin<Box<dyn Future<Output = ()>>>,
 }
 
 impl Task {
-    fn new(future: impl Future<Output = ()> + 'static) -> Self {
-        Self { id: TaskId::new(), future: Box::pin(future) }
+    fn new(
+        id: TaskId, future: impl Future<Output = ()> + 'static,
+        priority: Priority,
+    ) -> Self {
+        Self {
+            id,
+            priority,
+            stats: Rc::new(Cell::new(TaskStats::default())),
+            future: Box::pin(future),
+        }
     }
 
     fn poll(&mut self, context: &mut Context) -> Poll<()> {
-        self.future.as_mut().poll(context)
+        let start = timestamp();
+        let result = self.future.as_mut().poll(context);
+
+        let mut stats = self.stats.get();
+        stats.polls += 1;
+        stats.poll_cycles += timestamp().wrapping_sub(start);
+        self.stats.set(stats);
+        result
     }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
-struct TaskId(u64);
+pub struct TaskId(u64);
 
 impl TaskId {
     fn new() -> Self {
@@ -32,4 +57,51 @@ impl TaskId {
         static NEXT_ID: AtomicU64 = AtomicU64::new(0);
         TaskId(NEXT_ID.fetch_add(1, Ordering::Relaxed))
     }
+
+    pub fn as_u64(self) -> u64 {
+        self.0
+    }
+}
+
+/// The scheduling priority of a task.
+///
+/// Whenever the executor picks the next task to poll, ready tasks of a higher
+/// priority are always chosen before those of a lower one.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    Low    = 0,
+    Normal = 1,
+    High   = 2,
+}
+
+impl Priority {
+    /// Every priority, from highest to lowest.
+    const ALL: [Priority; 3] =
+        [Priority::High, Priority::Normal, Priority::Low];
+
+    fn as_usize(self) -> usize {
+        self as usize
+    }
+}
+
+impl Default for Priority {
+    fn default() -> Self {
+        Priority::Normal
+    }
+}
+
+/// Per-task accounting, as reported by `Executor::task_stats` and
+/// `Spawner::task_stats`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TaskStats {
+    /// Number of times the task has been polled.
+    pub polls:       u64,
+    /// Total time spent inside the task's `poll`, in TSC cycles.
+    pub poll_cycles: u64,
+}
+
+/// Reads the CPU's timestamp counter.
+fn timestamp() -> u64 {
+    unsafe { core::arch::x86_64::_rdtsc() }
 }
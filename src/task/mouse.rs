@@ -0,0 +1,269 @@
+use core::pin::Pin;
+use core::sync::atomic::{AtomicU64, Ordering};
+use core::task::{Context, Poll};
+
+use conquer_once::spin::OnceCell;
+use crossbeam_queue::ArrayQueue;
+use futures_util::stream::{Stream, StreamExt};
+use futures_util::task::AtomicWaker;
+
+use super::input::{InputEvent, Interest, Subscription};
+use crate::ps2::{self, DeviceType, Ps2Port};
+use crate::vga::{self, VGA_WRITER};
+
+/// How far the mouse has to move to cross one character cell of the screen.
+const COUNTS_PER_COLUMN: i32 = 8;
+const COUNTS_PER_ROW: i32 = 16;
+
+/// Draws a cursor on the VGA console that follows the mouse, by swapping the
+/// colours of the character underneath it.
+///
+/// Text that scrolls past the cursor will take the highlight along with it;
+/// this is only meant to show that the mouse works.
+pub async fn draw_cursor() {
+    let mut input = Subscription::new(Interest::MOUSE);
+
+    let max_x = vga::BUFFER_WIDTH as i32 * COUNTS_PER_COLUMN - 1;
+    let max_y = vga::BUFFER_HEIGHT as i32 * COUNTS_PER_ROW - 1;
+    let (mut x, mut y) = (max_x / 2, max_y / 2);
+    let mut drawn_at = cell(x, y);
+    VGA_WRITER.lock().invert_colors(drawn_at.0, drawn_at.1);
+
+    while let Some(event) = input.next().await {
+        let event = match event {
+            InputEvent::Mouse(event) => event,
+            InputEvent::Key(_) => continue,
+        };
+
+        // the mouse counts upwards as positive, unlike the screen
+        x = (x + i32::from(event.dx)).max(0).min(max_x);
+        y = (y - i32::from(event.dy)).max(0).min(max_y);
+
+        // let the wheel nudge the cursor a row at a time
+        y = (y + i32::from(event.dz) * COUNTS_PER_ROW).max(0).min(max_y);
+
+        let position = cell(x, y);
+        if position != drawn_at {
+            let mut writer = VGA_WRITER.lock();
+            writer.invert_colors(drawn_at.0, drawn_at.1);
+            writer.invert_colors(position.0, position.1);
+            drawn_at = position;
+        }
+    }
+}
+
+/// Returns the (row, column) of the character cell under a cursor position.
+fn cell(x: i32, y: i32) -> (usize, usize) {
+    ((y / COUNTS_PER_ROW) as usize, (x / COUNTS_PER_COLUMN) as usize)
+}
+
+/// The state of the mouse's buttons.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MouseButtons {
+    pub left:   bool,
+    pub right:  bool,
+    pub middle: bool,
+}
+
+/// A movement packet from the mouse.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MouseEvent {
+    /// Horizontal movement, positive to the right.
+    pub dx:      i16,
+    /// Vertical movement, positive upwards.
+    pub dy:      i16,
+    /// Scroll wheel movement, positive towards the user. Always 0 for mice
+    /// without a wheel.
+    pub dz:      i8,
+    pub buttons: MouseButtons,
+}
+
+/// Assembles the bytes sent by the mouse into `MouseEvent`s.
+pub struct PacketDecoder {
+    packet:     [u8; 4],
+    len:        usize,
+    packet_len: usize,
+}
+
+impl PacketDecoder {
+    /// Bit 3 of a packet's first byte is always set.
+    const ALWAYS_ONE: u8 = 1 << 3;
+    const X_SIGN: u8 = 1 << 4;
+    const Y_SIGN: u8 = 1 << 5;
+    const X_OVERFLOW: u8 = 1 << 6;
+    const Y_OVERFLOW: u8 = 1 << 7;
+
+    /// Creates a decoder for a mouse that sends 3-byte packets, or 4-byte ones
+    /// if it has a scroll wheel turned on.
+    pub fn new(has_wheel: bool) -> Self {
+        PacketDecoder {
+            packet:     [0; 4],
+            len:        0,
+            packet_len: if has_wheel { 4 } else { 3 },
+        }
+    }
+
+    /// Feeds in a byte, returning an event if it completes a packet.
+    pub fn add_byte(&mut self, byte: u8) -> Option<MouseEvent> {
+        // if we've lost track of where packets start, skip bytes until one
+        // looks like the start of a packet again
+        if self.len == 0 && byte & Self::ALWAYS_ONE == 0 {
+            return None;
+        }
+
+        self.packet[self.len] = byte;
+        self.len += 1;
+        if self.len < self.packet_len {
+            return None;
+        }
+        self.len = 0;
+
+        let flags = self.packet[0];
+        let movement = |value: u8, sign: u8, overflow: u8| {
+            if flags & overflow != 0 {
+                0
+            }
+            else if flags & sign != 0 {
+                i16::from(value) - 0x100
+            }
+            else {
+                i16::from(value)
+            }
+        };
+
+        let dz = if self.packet_len == 4 { self.packet[3] as i8 } else { 0 };
+
+        Some(MouseEvent {
+            dx: movement(self.packet[1], Self::X_SIGN, Self::X_OVERFLOW),
+            dy: movement(self.packet[2], Self::Y_SIGN, Self::Y_OVERFLOW),
+            dz,
+            buttons: MouseButtons {
+                left:   flags & 1 << 0 != 0,
+                right:  flags & 1 << 1 != 0,
+                middle: flags & 1 << 2 != 0,
+            },
+        })
+    }
+}
+
+static MOUSE_QUEUE: OnceCell<ArrayQueue<u8>> = OnceCell::uninit();
+static WAKER: AtomicWaker = AtomicWaker::new();
+static DROPPED_BYTES: AtomicU64 = AtomicU64::new(0);
+
+/// Called by the mouse interrupt handler
+///
+/// Must not block or allocate. Like `keyboard::push_scancode`, bytes that
+/// don't fit in the queue are only counted here, and reported later.
+pub(crate) fn push_byte(byte: u8) {
+    // until a `MouseStream` exists, nobody is interested in the mouse
+    if let Ok(queue) = MOUSE_QUEUE.try_get() {
+        if let Ok(()) = queue.push(byte) {
+            WAKER.wake();
+        }
+        else {
+            DROPPED_BYTES.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Returns the number of bytes from the mouse dropped because the queue was
+/// full.
+pub fn dropped_bytes() -> u64 {
+    DROPPED_BYTES.load(Ordering::Relaxed)
+}
+
+/// A stream of packets from the PS/2 mouse.
+///
+/// Only one of these can ever be created, which `input::dispatch` does; other
+/// tasks should use an `input::Subscription` instead.
+pub struct MouseStream {
+    decoder:          PacketDecoder,
+    /// The value of `dropped_bytes` when it was last reported.
+    reported_dropped: u64,
+}
+
+impl MouseStream {
+    pub fn new() -> Self {
+        MOUSE_QUEUE
+            .try_init_once(|| ArrayQueue::new(128))
+            .expect("MouseStream::new should only be called once");
+
+        let device = ps2::CONTROLLER.lock().device(Ps2Port::Second);
+        let has_wheel = matches!(
+            device,
+            Some(DeviceType::ScrollMouse) | Some(DeviceType::FiveButtonMouse)
+        );
+        MouseStream {
+            decoder:          PacketDecoder::new(has_wheel),
+            reported_dropped: 0,
+        }
+    }
+}
+
+impl Default for MouseStream {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Stream for MouseStream {
+    type Item = MouseEvent;
+
+    fn poll_next(
+        mut self: Pin<&mut Self>, cx: &mut Context,
+    ) -> Poll<Option<MouseEvent>> {
+        let queue = MOUSE_QUEUE.try_get().expect("mouse queue not initialized");
+
+        let dropped = dropped_bytes();
+        if dropped != self.reported_dropped {
+            log::warn!(
+                "mouse queue full; dropped {} bytes",
+                dropped - self.reported_dropped
+            );
+            self.reported_dropped = dropped;
+        }
+
+        loop {
+            let byte = match queue.pop() {
+                Some(byte) => byte,
+                None => {
+                    WAKER.register(cx.waker());
+                    match queue.pop() {
+                        Some(byte) => {
+                            WAKER.take();
+                            byte
+                        },
+                        None => return Poll::Pending,
+                    }
+                },
+            };
+
+            if let Some(event) = self.decoder.add_byte(byte) {
+                return Poll::Ready(Some(event));
+            }
+        }
+    }
+}
+
+
+#[test_case]
+fn test_packet_decoding() {
+    let mut decoder = PacketDecoder::new(false);
+
+    // a stray byte without bit 3 set is skipped
+    assert_eq!(decoder.add_byte(0x00), None);
+    assert_eq!(decoder.add_byte(0b0001_1001), None);
+    assert_eq!(decoder.add_byte(0xfe), None);
+    let event = decoder.add_byte(0x05).expect("packet should be complete");
+    assert_eq!((event.dx, event.dy, event.dz), (-2, 5, 0));
+    assert!(event.buttons.left && !event.buttons.right);
+
+    // movement is dropped when it overflows
+    let mut decoder = PacketDecoder::new(true);
+    for &byte in &[0b0100_1010, 0x80, 0x10] {
+        assert_eq!(decoder.add_byte(byte), None);
+    }
+    let event = decoder.add_byte(0xff).expect("packet should be complete");
+    assert_eq!((event.dx, event.dy, event.dz), (0, 16, -1));
+    assert!(event.buttons.right);
+}
@@ -0,0 +1,300 @@
+//! Synchronisation primitives for tasks.
+//!
+//! Unlike `spin::Mutex`, these never busy-wait. A task that has to wait is
+//! parked through its `Waker` and only polled again once it can make progress,
+//! so it's fine to hold their guards across an `.await`.
+
+pub mod mpsc;
+mod mutex;
+mod notify;
+pub mod oneshot;
+mod rwlock;
+mod semaphore;
+
+use alloc::collections::VecDeque;
+use core::task::Waker;
+
+pub use mutex::{Mutex, MutexGuard};
+pub use notify::{Notified, Notify};
+pub use rwlock::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+pub use semaphore::{Acquire, Semaphore, SemaphorePermit};
+
+/// A FIFO queue of parked tasks.
+///
+/// Each waiting future owns a slot (an `Option<u64>`) that holds its place in
+/// the queue. Waking a waiter removes it from the queue, which is how the
+/// future can later tell that it was woken on purpose.
+struct WaitQueue {
+    next_id: u64,
+    waiters: VecDeque<(u64, Waker)>,
+}
+
+impl WaitQueue {
+    fn new() -> Self {
+        WaitQueue { next_id: 0, waiters: VecDeque::new() }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.waiters.is_empty()
+    }
+
+    fn contains(&self, id: u64) -> bool {
+        self.waiters.iter().any(|(waiter, _)| *waiter == id)
+    }
+
+    /// Returns true if the slot's waiter has been woken through `wake_one` or
+    /// `wake_all` since it last registered.
+    fn was_woken(&self, slot: &Option<u64>) -> bool {
+        match slot {
+            Some(id) => !self.contains(*id),
+            None => false,
+        }
+    }
+
+    /// Parks the slot's waiter, or updates its waker if it's already parked.
+    ///
+    /// A waiter that was woken but couldn't make progress goes back to the
+    /// front, so that it doesn't lose its place.
+    fn register(&mut self, slot: &mut Option<u64>, waker: &Waker) {
+        if let Some(id) = *slot {
+            if let Some((_, old)) =
+                self.waiters.iter_mut().find(|(waiter, _)| *waiter == id)
+            {
+                if !old.will_wake(waker) {
+                    *old = waker.clone();
+                }
+            }
+            else {
+                self.waiters.push_front((id, waker.clone()));
+            }
+        }
+        else {
+            let id = self.next_id;
+            self.next_id += 1;
+            self.waiters.push_back((id, waker.clone()));
+            *slot = Some(id);
+        }
+    }
+
+    /// Clears the slot of a waiter that has finished waiting.
+    fn finish(&mut self, slot: &mut Option<u64>) {
+        if let Some(id) = slot.take() {
+            self.waiters.retain(|(waiter, _)| *waiter != id);
+        }
+    }
+
+    /// Clears the slot of a waiter that gave up. If it had already been woken,
+    /// the wakeup is handed on to the next waiter so that it isn't lost.
+    fn cancel(&mut self, slot: &mut Option<u64>) {
+        if self.was_woken(slot) {
+            self.wake_one();
+        }
+        self.finish(slot);
+    }
+
+    fn wake_one(&mut self) {
+        if let Some((_, waker)) = self.waiters.pop_front() {
+            waker.wake();
+        }
+    }
+
+    fn wake_all(&mut self) {
+        for (_, waker) in self.waiters.drain(..) {
+            waker.wake();
+        }
+    }
+}
+
+
+#[test_case]
+fn test_mutex_across_await() {
+    use alloc::rc::Rc;
+
+    use crate::task::Executor;
+
+    let mutex = Rc::new(Mutex::new(0));
+    let notify = Rc::new(Notify::new());
+    let mut executor = Executor::new(10);
+
+    // holds the lock across an await point
+    let (m, n) = (mutex.clone(), notify.clone());
+    executor
+        .spawn(async move {
+            let mut guard = m.lock().await;
+            n.notified().await;
+            *guard += 1;
+        })
+        .unwrap();
+    // has to wait for the first task to let go of the lock
+    let m = mutex.clone();
+    executor.spawn(async move { *m.lock().await *= 10 }).unwrap();
+
+    executor.run_until_idle();
+    assert!(mutex.try_lock().is_none());
+
+    notify.notify_one();
+    executor.run_until_idle();
+    assert_eq!(*mutex.try_lock().unwrap(), 10);
+}
+
+#[test_case]
+fn test_channels() {
+    use alloc::rc::Rc;
+    use alloc::vec::Vec;
+    use core::cell::RefCell;
+
+    use crate::task::Executor;
+
+    let (tx, mut rx) = mpsc::channel(2);
+    let (done_tx, done_rx) = oneshot::channel();
+    let mut executor = Executor::new(10);
+
+    // sends more values than the channel can buffer
+    executor
+        .spawn(async move {
+            for i in 0..5 {
+                tx.send(i).await.unwrap();
+            }
+        })
+        .unwrap();
+    executor
+        .spawn(async move {
+            let mut received = Vec::new();
+            while let Some(i) = rx.recv().await {
+                received.push(i);
+            }
+            done_tx.send(received).unwrap();
+        })
+        .unwrap();
+    let result = Rc::new(RefCell::new(None));
+    let r = result.clone();
+    executor
+        .spawn(async move { *r.borrow_mut() = Some(done_rx.await) })
+        .unwrap();
+
+    executor.run_until_idle();
+    assert_eq!(*result.borrow(), Some(Ok(alloc::vec![0, 1, 2, 3, 4])));
+    assert_eq!(executor.task_count(), 0);
+}
+
+#[test_case]
+fn test_rwlock() {
+    use alloc::rc::Rc;
+    use alloc::vec::Vec;
+    use core::cell::Cell;
+
+    use crate::task::Executor;
+
+    let lock = Rc::new(RwLock::new(0));
+    let mut executor = Executor::new(10);
+
+    let readers: Vec<_> =
+        (0..rwlock::MAX_READERS).map(|_| lock.try_read().unwrap()).collect();
+    assert!(lock.try_read().is_none());
+    assert!(lock.try_write().is_none());
+
+    // the writer has to wait for every reader to finish
+    let l = lock.clone();
+    executor.spawn(async move { *l.write().await += 1 }).unwrap();
+    executor.run_until_idle();
+    assert_eq!(*readers[0], 0);
+
+    // and a reader that comes after it waits for the writer
+    let seen = Rc::new(Cell::new(None));
+    let (l, s) = (lock.clone(), seen.clone());
+    executor.spawn(async move { s.set(Some(*l.read().await)) }).unwrap();
+    executor.run_until_idle();
+    assert_eq!(seen.get(), None);
+
+    drop(readers);
+    executor.run_until_idle();
+    assert_eq!(seen.get(), Some(1));
+    assert_eq!(executor.task_count(), 0);
+    assert!(lock.try_write().is_some());
+}
+
+#[test_case]
+fn test_semaphore() {
+    use alloc::rc::Rc;
+    use core::cell::Cell;
+
+    use crate::task::Executor;
+
+    let semaphore = Rc::new(Semaphore::new(3));
+    let mut executor = Executor::new(10);
+
+    let permit = semaphore.try_acquire_many(2).unwrap();
+    assert_eq!(semaphore.available_permits(), 1);
+    assert!(semaphore.try_acquire_many(2).is_none());
+
+    // permits are given back when they're dropped, to whoever is waiting
+    let acquired = Rc::new(Cell::new(false));
+    let (sem, a) = (semaphore.clone(), acquired.clone());
+    executor
+        .spawn(async move {
+            let _permit = sem.acquire_many(3).await;
+            a.set(true);
+        })
+        .unwrap();
+    executor.run_until_idle();
+    assert!(!acquired.get());
+
+    drop(permit);
+    executor.run_until_idle();
+    assert!(acquired.get());
+    assert_eq!(semaphore.available_permits(), 3);
+
+    // unless they're forgotten
+    semaphore.try_acquire().unwrap().forget();
+    assert_eq!(semaphore.available_permits(), 2);
+}
+
+#[test_case]
+fn test_notify() {
+    use alloc::rc::Rc;
+    use core::cell::Cell;
+
+    use crate::task::Executor;
+
+    let notify = Rc::new(Notify::new());
+    let woken = Rc::new(Cell::new(0));
+    let mut executor = Executor::new(10);
+    let wait = |executor: &mut Executor| {
+        let (n, w) = (notify.clone(), woken.clone());
+        executor
+            .spawn(async move {
+                n.notified().await;
+                w.set(w.get() + 1);
+            })
+            .unwrap();
+    };
+
+    for _ in 0..3 {
+        wait(&mut executor);
+    }
+    executor.run_until_idle();
+    assert_eq!(woken.get(), 0);
+
+    notify.notify_one();
+    executor.run_until_idle();
+    assert_eq!(woken.get(), 1);
+
+    notify.notify_waiters();
+    executor.run_until_idle();
+    assert_eq!(woken.get(), 3);
+
+    // with nobody waiting, only `notify_one` is remembered
+    notify.notify_waiters();
+    wait(&mut executor);
+    executor.run_until_idle();
+    assert_eq!(woken.get(), 3);
+
+    notify.notify_one();
+    notify.notify_one();
+    executor.run_until_idle();
+    assert_eq!(woken.get(), 4);
+    wait(&mut executor);
+    executor.run_until_idle();
+    assert_eq!(woken.get(), 5);
+    assert_eq!(executor.task_count(), 0);
+}
@@ -0,0 +1,234 @@
+//! A bounded multi-producer, single-consumer channel.
+
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use core::fmt;
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll, Waker};
+
+use spin::Mutex;
+
+use super::WaitQueue;
+
+struct State<T> {
+    buffer:        VecDeque<T>,
+    capacity:      usize,
+    senders:       usize,
+    receiver_open: bool,
+    recv_waker:    Option<Waker>,
+    /// Senders waiting for room in the buffer.
+    send_waiters:  WaitQueue,
+}
+
+impl<T> State<T> {
+    fn push(&mut self, value: T) {
+        self.buffer.push_back(value);
+        if let Some(waker) = self.recv_waker.take() {
+            waker.wake();
+        }
+    }
+
+    /// Pops the oldest value, making room for a waiting sender.
+    fn pop(&mut self) -> Option<T> {
+        let value = self.buffer.pop_front()?;
+        self.send_waiters.wake_one();
+        Some(value)
+    }
+}
+
+/// Creates a channel that buffers up to `capacity` values.
+pub fn channel<T>(capacity: usize) -> (Sender<T>, Receiver<T>) {
+    assert!(capacity > 0, "channel capacity must be at least 1");
+
+    let state = Arc::new(Mutex::new(State {
+        buffer: VecDeque::with_capacity(capacity),
+        capacity,
+        senders: 1,
+        receiver_open: true,
+        recv_waker: None,
+        send_waiters: WaitQueue::new(),
+    }));
+    (Sender { state: state.clone() }, Receiver { state })
+}
+
+/// Returned when sending to a channel whose receiver has been dropped. Holds
+/// the value that couldn't be sent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SendError<T>(pub T);
+
+impl<T> fmt::Display for SendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "channel closed")
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrySendError<T> {
+    /// The buffer is full.
+    Full(T),
+    /// The receiver has been dropped.
+    Closed(T),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TryRecvError {
+    /// Nothing has been sent yet.
+    Empty,
+    /// Every sender has been dropped, and the buffer is empty.
+    Closed,
+}
+
+pub struct Sender<T> {
+    state: Arc<Mutex<State<T>>>,
+}
+
+impl<T> Sender<T> {
+    /// Sends a value, waiting for room in the buffer if it's full.
+    pub fn send(&self, value: T) -> SendFuture<'_, T> {
+        SendFuture { sender: self, value: Some(value), slot: None }
+    }
+
+    /// Sends a value if there's room in the buffer right now.
+    pub fn try_send(&self, value: T) -> Result<(), TrySendError<T>> {
+        let mut state = self.state.lock();
+        if !state.receiver_open {
+            Err(TrySendError::Closed(value))
+        }
+        else if state.buffer.len() >= state.capacity
+            || !state.send_waiters.is_empty()
+        {
+            Err(TrySendError::Full(value))
+        }
+        else {
+            state.push(value);
+            Ok(())
+        }
+    }
+
+    /// Returns true if the receiver has been dropped.
+    pub fn is_closed(&self) -> bool {
+        !self.state.lock().receiver_open
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        self.state.lock().senders += 1;
+        Sender { state: self.state.clone() }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        let mut state = self.state.lock();
+        state.senders -= 1;
+        if state.senders == 0 {
+            // let the receiver see that the channel is closed
+            if let Some(waker) = state.recv_waker.take() {
+                waker.wake();
+            }
+        }
+    }
+}
+
+/// Future returned by `Sender::send`.
+pub struct SendFuture<'a, T> {
+    sender: &'a Sender<T>,
+    value:  Option<T>,
+    slot:   Option<u64>,
+}
+
+// `value` is never pinned, so it's fine to move it out of a pinned future.
+impl<T> Unpin for SendFuture<'_, T> {}
+
+impl<T> Future for SendFuture<'_, T> {
+    type Output = Result<(), SendError<T>>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let this = &mut *self;
+        let mut state = this.sender.state.lock();
+        let value =
+            this.value.take().expect("SendFuture polled after completion");
+
+        if !state.receiver_open {
+            state.send_waiters.finish(&mut this.slot);
+            return Poll::Ready(Err(SendError(value)));
+        }
+
+        let our_turn = state.send_waiters.is_empty()
+            || state.send_waiters.was_woken(&this.slot);
+        if our_turn && state.buffer.len() < state.capacity {
+            state.send_waiters.finish(&mut this.slot);
+            state.push(value);
+            if state.buffer.len() < state.capacity {
+                state.send_waiters.wake_one();
+            }
+            Poll::Ready(Ok(()))
+        }
+        else {
+            this.value = Some(value);
+            state.send_waiters.register(&mut this.slot, cx.waker());
+            Poll::Pending
+        }
+    }
+}
+
+impl<T> Drop for SendFuture<'_, T> {
+    fn drop(&mut self) {
+        if self.slot.is_some() {
+            self.sender.state.lock().send_waiters.cancel(&mut self.slot);
+        }
+    }
+}
+
+pub struct Receiver<T> {
+    state: Arc<Mutex<State<T>>>,
+}
+
+impl<T> Receiver<T> {
+    /// Waits for the next value. Returns `None` once every sender has been
+    /// dropped and the buffer is empty.
+    pub async fn recv(&mut self) -> Option<T> {
+        futures_util::future::poll_fn(|cx| self.poll_recv(cx)).await
+    }
+
+    pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        let mut state = self.state.lock();
+        match state.pop() {
+            Some(value) => Ok(value),
+            None if state.senders == 0 => Err(TryRecvError::Closed),
+            None => Err(TryRecvError::Empty),
+        }
+    }
+
+    pub fn poll_recv(&mut self, cx: &mut Context) -> Poll<Option<T>> {
+        let mut state = self.state.lock();
+        match state.pop() {
+            Some(value) => Poll::Ready(Some(value)),
+            None if state.senders == 0 => Poll::Ready(None),
+            None => {
+                state.recv_waker = Some(cx.waker().clone());
+                Poll::Pending
+            },
+        }
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        let mut state = self.state.lock();
+        state.receiver_open = false;
+        state.send_waiters.wake_all();
+    }
+}
+
+impl<T> futures_util::stream::Stream for Receiver<T> {
+    type Item = T;
+
+    fn poll_next(
+        mut self: Pin<&mut Self>, cx: &mut Context,
+    ) -> Poll<Option<T>> {
+        self.poll_recv(cx)
+    }
+}
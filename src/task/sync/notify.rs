@@ -0,0 +1,93 @@
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+
+use spin::Mutex;
+
+use super::WaitQueue;
+
+struct State {
+    /// Set by `notify_one` when nobody is waiting, so that the next call to
+    /// `notified` returns straight away.
+    permit:  bool,
+    waiters: WaitQueue,
+}
+
+/// Wakes tasks waiting for an event.
+pub struct Notify {
+    state: Mutex<State>,
+}
+
+impl Notify {
+    pub fn new() -> Self {
+        Notify {
+            state: Mutex::new(State {
+                permit:  false,
+                waiters: WaitQueue::new(),
+            }),
+        }
+    }
+
+    /// Wakes the longest-waiting task. If no task is waiting, the next one to
+    /// call `notified` won't have to wait at all.
+    pub fn notify_one(&self) {
+        let mut state = self.state.lock();
+        if state.waiters.is_empty() {
+            state.permit = true;
+        }
+        else {
+            state.waiters.wake_one();
+        }
+    }
+
+    /// Wakes every task that is currently waiting.
+    pub fn notify_waiters(&self) {
+        self.state.lock().waiters.wake_all();
+    }
+
+    /// Waits until notified.
+    pub fn notified(&self) -> Notified<'_> {
+        Notified { notify: self, slot: None }
+    }
+}
+
+impl Default for Notify {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Future returned by `Notify::notified`.
+pub struct Notified<'a> {
+    notify: &'a Notify,
+    slot:   Option<u64>,
+}
+
+impl Future for Notified<'_> {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        let notify = self.notify;
+        let mut state = notify.state.lock();
+
+        if state.waiters.was_woken(&self.slot) {
+            self.slot = None;
+            return Poll::Ready(());
+        }
+        if self.slot.is_none() && state.permit {
+            state.permit = false;
+            return Poll::Ready(());
+        }
+
+        state.waiters.register(&mut self.slot, cx.waker());
+        Poll::Pending
+    }
+}
+
+impl Drop for Notified<'_> {
+    fn drop(&mut self) {
+        if self.slot.is_some() {
+            self.notify.state.lock().waiters.cancel(&mut self.slot);
+        }
+    }
+}
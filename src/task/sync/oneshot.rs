@@ -0,0 +1,111 @@
+//! A channel for sending a single value between tasks.
+
+use alloc::sync::Arc;
+use core::fmt;
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll, Waker};
+
+use spin::Mutex;
+
+struct State<T> {
+    value:         Option<T>,
+    sender_open:   bool,
+    receiver_open: bool,
+    recv_waker:    Option<Waker>,
+}
+
+pub fn channel<T>() -> (Sender<T>, Receiver<T>) {
+    let state = Arc::new(Mutex::new(State {
+        value:         None,
+        sender_open:   true,
+        receiver_open: true,
+        recv_waker:    None,
+    }));
+    (Sender { state: state.clone() }, Receiver { state })
+}
+
+/// Returned by a `Receiver` whose `Sender` was dropped without sending.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecvError;
+
+impl fmt::Display for RecvError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "sender dropped without sending")
+    }
+}
+
+pub struct Sender<T> {
+    state: Arc<Mutex<State<T>>>,
+}
+
+impl<T> Sender<T> {
+    /// Sends the value. If the receiver has already been dropped, the value
+    /// is handed back.
+    pub fn send(self, value: T) -> Result<(), T> {
+        let mut state = self.state.lock();
+        if !state.receiver_open {
+            return Err(value);
+        }
+
+        state.value = Some(value);
+        if let Some(waker) = state.recv_waker.take() {
+            waker.wake();
+        }
+        Ok(())
+    }
+
+    /// Returns true if the receiver has been dropped.
+    pub fn is_closed(&self) -> bool {
+        !self.state.lock().receiver_open
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        let mut state = self.state.lock();
+        state.sender_open = false;
+        if let Some(waker) = state.recv_waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+/// Resolves to the sent value.
+pub struct Receiver<T> {
+    state: Arc<Mutex<State<T>>>,
+}
+
+impl<T> Receiver<T> {
+    /// Takes the value if it has been sent already.
+    pub fn try_recv(&mut self) -> Option<Result<T, RecvError>> {
+        let mut state = self.state.lock();
+        match state.value.take() {
+            Some(value) => Some(Ok(value)),
+            None if !state.sender_open => Some(Err(RecvError)),
+            None => None,
+        }
+    }
+}
+
+impl<T> Future for Receiver<T> {
+    type Output = Result<T, RecvError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let mut state = self.state.lock();
+        match state.value.take() {
+            Some(value) => Poll::Ready(Ok(value)),
+            None if !state.sender_open => Poll::Ready(Err(RecvError)),
+            None => {
+                state.recv_waker = Some(cx.waker().clone());
+                Poll::Pending
+            },
+        }
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        self.state.lock().receiver_open = false;
+    }
+}
@@ -0,0 +1,130 @@
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+
+use spin::Mutex;
+
+use super::WaitQueue;
+
+struct State {
+    permits: usize,
+    waiters: WaitQueue,
+}
+
+/// A counting semaphore.
+///
+/// Waiters are served in FIFO order: once a task is waiting, tasks that arrive
+/// later can't take permits ahead of it, even if they'd need fewer.
+pub struct Semaphore {
+    state: Mutex<State>,
+}
+
+impl Semaphore {
+    pub fn new(permits: usize) -> Self {
+        Semaphore {
+            state: Mutex::new(State { permits, waiters: WaitQueue::new() }),
+        }
+    }
+
+    pub fn available_permits(&self) -> usize {
+        self.state.lock().permits
+    }
+
+    /// Returns `n` permits to the semaphore, waking waiters as needed.
+    pub fn add_permits(&self, n: usize) {
+        let mut state = self.state.lock();
+        state.permits += n;
+        state.waiters.wake_one();
+    }
+
+    /// Waits for a single permit.
+    pub fn acquire(&self) -> Acquire<'_> {
+        self.acquire_many(1)
+    }
+
+    /// Waits for `n` permits, which are taken all at once.
+    pub fn acquire_many(&self, n: usize) -> Acquire<'_> {
+        Acquire { semaphore: self, permits: n, slot: None }
+    }
+
+    /// Takes a single permit if one is available right now.
+    pub fn try_acquire(&self) -> Option<SemaphorePermit<'_>> {
+        self.try_acquire_many(1)
+    }
+
+    /// Takes `n` permits if they're available right now.
+    pub fn try_acquire_many(&self, n: usize) -> Option<SemaphorePermit<'_>> {
+        let mut state = self.state.lock();
+        if state.permits >= n && state.waiters.is_empty() {
+            state.permits -= n;
+            Some(SemaphorePermit { semaphore: self, permits: n })
+        }
+        else {
+            None
+        }
+    }
+}
+
+/// Future returned by `Semaphore::acquire` and `Semaphore::acquire_many`.
+pub struct Acquire<'a> {
+    semaphore: &'a Semaphore,
+    permits:   usize,
+    slot:      Option<u64>,
+}
+
+impl<'a> Future for Acquire<'a> {
+    type Output = SemaphorePermit<'a>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let semaphore = self.semaphore;
+        let n = self.permits;
+        let mut state = semaphore.state.lock();
+
+        // only take permits if nobody is waiting ahead of us
+        let our_turn =
+            state.waiters.is_empty() || state.waiters.was_woken(&self.slot);
+
+        if our_turn && state.permits >= n {
+            state.permits -= n;
+            state.waiters.finish(&mut self.slot);
+            if state.permits > 0 {
+                // there may be enough left over for the next waiter
+                state.waiters.wake_one();
+            }
+            Poll::Ready(SemaphorePermit { semaphore, permits: n })
+        }
+        else {
+            state.waiters.register(&mut self.slot, cx.waker());
+            Poll::Pending
+        }
+    }
+}
+
+impl Drop for Acquire<'_> {
+    fn drop(&mut self) {
+        if self.slot.is_some() {
+            self.semaphore.state.lock().waiters.cancel(&mut self.slot);
+        }
+    }
+}
+
+/// Permits taken from a `Semaphore`. They're given back when this is dropped.
+pub struct SemaphorePermit<'a> {
+    semaphore: &'a Semaphore,
+    permits:   usize,
+}
+
+impl SemaphorePermit<'_> {
+    /// Keeps the permits taken for good, rather than giving them back.
+    pub fn forget(mut self) {
+        self.permits = 0;
+    }
+}
+
+impl Drop for SemaphorePermit<'_> {
+    fn drop(&mut self) {
+        if self.permits > 0 {
+            self.semaphore.add_permits(self.permits);
+        }
+    }
+}
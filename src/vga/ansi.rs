@@ -0,0 +1,173 @@
+//! A parser for the ANSI/VT100 escape sequences understood by `VGAWriter`.
+
+const ESC: char = '\x1b';
+
+/// The most parameters a sequence can have. Any more are ignored.
+const MAX_PARAMS: usize = 8;
+
+/// What the writer should do with a character it's been given.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Action {
+    /// Nothing yet; the character was part of an escape sequence.
+    None,
+    /// The character isn't part of an escape sequence, and should be written.
+    Print(char),
+    /// A complete control sequence (`ESC [ ...`).
+    Csi(Csi),
+}
+
+/// A control sequence, made up of numeric parameters and a final byte saying
+/// what to do with them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct Csi {
+    params:             [u16; MAX_PARAMS],
+    len:                usize,
+    /// Whether the parameters started with `?`, as used by the DEC private
+    /// modes.
+    pub(super) private: bool,
+    pub(super) command: char,
+}
+
+impl Csi {
+    const fn new() -> Self {
+        Csi {
+            params:  [0; MAX_PARAMS],
+            len:     0,
+            private: false,
+            command: '\0',
+        }
+    }
+
+    pub(super) fn params(&self) -> &[u16] {
+        &self.params[..self.len]
+    }
+
+    /// Returns the parameter at the given index, or `default` if it was left
+    /// out or given as 0.
+    pub(super) fn param_or(&self, index: usize, default: u16) -> u16 {
+        match self.params().get(index) {
+            Some(&param) if param != 0 => param,
+            _ => default,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Ground,
+    /// After an `ESC`.
+    Escape,
+    /// Inside an escape sequence other than a control sequence, such as the
+    /// `ESC ( B` used to pick a character set.
+    EscapeIntermediate,
+    /// Inside a control sequence.
+    Csi,
+}
+
+pub(super) struct Parser {
+    state: State,
+    csi:   Csi,
+}
+
+impl Parser {
+    pub(super) const fn new() -> Self {
+        Parser { state: State::Ground, csi: Csi::new() }
+    }
+
+    pub(super) fn advance(&mut self, c: char) -> Action {
+        match (self.state, c) {
+            (State::Ground, ESC) => {
+                self.state = State::Escape;
+                Action::None
+            },
+            (State::Ground, c) => Action::Print(c),
+
+            (State::Escape, '[') => {
+                self.state = State::Csi;
+                self.csi = Csi::new();
+                Action::None
+            },
+            // any other escape sequences aren't supported, and are dropped
+            (State::Escape, ' '..='/') => {
+                self.state = State::EscapeIntermediate;
+                Action::None
+            },
+            (State::EscapeIntermediate, ' '..='/') => Action::None,
+            (State::Escape, _) | (State::EscapeIntermediate, _) => {
+                self.state = State::Ground;
+                Action::None
+            },
+
+            (State::Csi, '0'..='9') => {
+                let csi = &mut self.csi;
+                if csi.len == 0 {
+                    csi.len = 1;
+                }
+                if let Some(param) = csi.params.get_mut(csi.len - 1) {
+                    let digit = c as u16 - '0' as u16;
+                    *param = param.saturating_mul(10).saturating_add(digit);
+                }
+                Action::None
+            },
+            (State::Csi, ';') => {
+                let csi = &mut self.csi;
+                // the parameter before the `;` may have been left out
+                csi.len = (csi.len.max(1) + 1).min(MAX_PARAMS + 1);
+                Action::None
+            },
+            (State::Csi, '?') if self.csi.len == 0 => {
+                self.csi.private = true;
+                Action::None
+            },
+            (State::Csi, '@'..='~') => {
+                self.state = State::Ground;
+                self.csi.len = self.csi.len.min(MAX_PARAMS);
+                self.csi.command = c;
+                Action::Csi(self.csi)
+            },
+            // anything else means the sequence is malformed, so drop it
+            (State::Csi, _) => {
+                self.state = State::Ground;
+                Action::None
+            },
+        }
+    }
+}
+
+
+#[test_case]
+fn test_parser() {
+    let mut parser = Parser::new();
+    let mut parse = |s: &str| {
+        let mut actions = alloc::vec::Vec::new();
+        for c in s.chars() {
+            match parser.advance(c) {
+                Action::None => {},
+                action => actions.push(action),
+            }
+        }
+        actions
+    };
+
+    assert_eq!(parse("a\x1b[1;31mb"), [
+        Action::Print('a'),
+        Action::Csi(Csi {
+            params:  [1, 31, 0, 0, 0, 0, 0, 0],
+            len:     2,
+            private: false,
+            command: 'm',
+        }),
+        Action::Print('b')
+    ]);
+
+    let actions = parse("\x1b[;5H\x1b[?25l\x1b(Bc");
+    match actions[..] {
+        [Action::Csi(position), Action::Csi(mode), Action::Print('c')] => {
+            assert_eq!(position.param_or(0, 1), 1);
+            assert_eq!(position.param_or(1, 1), 5);
+            assert!(mode.private && mode.params() == [25]);
+            assert_eq!(mode.command, 'l');
+        },
+        _ => panic!("unexpected actions: {:?}", actions),
+    }
+}
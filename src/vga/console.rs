@@ -0,0 +1,156 @@
+//! Virtual consoles, each with its own text, cursor and colours, one of which
+//! is shown on the screen at a time.
+//!
+//! The console being shown writes straight to the VGA text buffer, while the
+//! others write to buffers of their own. Switching consoles swaps the contents
+//! of the two buffers, along with which console each belongs to.
+//!
+//! The shell runs on the first console, `dashboard` draws on the second, log
+//! records are written to the third, and the fourth has a task that echoes
+//! what's typed on it. Each console has its own scrollback, and keys only go
+//! to tasks reading from the console being shown.
+//!
+//! Once the display has left text mode, the text buffer isn't shown any more,
+//! and writing to it may draw over whatever is. `detach_screen` moves the
+//! shown console into a buffer of its own instead, to be drawn by something
+//! else.
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use lazy_static::lazy_static;
+
+use super::{Buffer, VGAChar, VGAWriter, BUFFER_HEIGHT, BUFFER_WIDTH};
+use crate::sync::IrqSafeMutex;
+
+/// The number of consoles, which are switched between with Alt+F1 onwards.
+pub const CONSOLE_COUNT: usize = 6;
+
+type Text = [[VGAChar; BUFFER_WIDTH]; BUFFER_HEIGHT];
+
+/// The text of the consoles that aren't being shown. Only ever accessed
+/// through the `buffer` of the console that it currently belongs to.
+static mut OFF_SCREEN: [Text; CONSOLE_COUNT - 1] =
+    [[[VGAChar::BLANK; BUFFER_WIDTH]; BUFFER_HEIGHT]; CONSOLE_COUNT - 1];
+
+/// The text of the console being shown, once the screen has been detached.
+static mut DETACHED: Text = [[VGAChar::BLANK; BUFFER_WIDTH]; BUFFER_HEIGHT];
+
+static ACTIVE: AtomicUsize = AtomicUsize::new(0);
+
+lazy_static! {
+    static ref CONSOLES: [IrqSafeMutex<VGAWriter>; CONSOLE_COUNT] = [
+        IrqSafeMutex::new(VGAWriter::default()),
+        off_screen(0),
+        off_screen(1),
+        off_screen(2),
+        off_screen(3),
+        off_screen(4),
+    ];
+}
+
+/// Creates a console that writes to the given off-screen buffer.
+fn off_screen(index: usize) -> IrqSafeMutex<VGAWriter> {
+    // each console is created once, and is given its own buffer
+    let buffer =
+        unsafe { &mut *(&mut OFF_SCREEN[index] as *mut Text as *mut Buffer) };
+    let mut writer = VGAWriter { buffer, ..VGAWriter::default() };
+    writer.cursor.active = false;
+    IrqSafeMutex::new(writer)
+}
+
+/// Returns the console with the given index, counting from 0.
+///
+/// Panics if `index` isn't less than `CONSOLE_COUNT`.
+pub fn console(index: usize) -> &'static IrqSafeMutex<VGAWriter> {
+    &CONSOLES[index]
+}
+
+/// Returns the index of the console being shown.
+pub fn active_console() -> usize {
+    ACTIVE.load(Ordering::Relaxed)
+}
+
+/// Gives access to the console being shown.
+pub fn with_screen<F: FnOnce(&VGAWriter) -> R, R>(f: F) -> R {
+    f(&CONSOLES[active_console()].lock())
+}
+
+/// Stops using the VGA text buffer, for when the display has been switched to
+/// a graphics mode. The console being shown is moved to a buffer of its own,
+/// which can be drawn using `with_screen`.
+///
+/// Must only be called once.
+pub fn detach_screen() {
+    let mut writer = CONSOLES[active_console()].lock();
+    let detached = unsafe { &mut *(&mut DETACHED as *mut Text as *mut Buffer) };
+    for row in 0..BUFFER_HEIGHT {
+        for col in 0..BUFFER_WIDTH {
+            let c = writer.buffer.chars[row][col].read();
+            detached.chars[row][col].write(c);
+        }
+    }
+    writer.buffer = detached;
+}
+
+/// Shows the console with the given index, hiding the one that was shown.
+///
+/// Panics if `index` isn't less than `CONSOLE_COUNT`.
+pub fn switch_console(index: usize) {
+    assert!(index < CONSOLE_COUNT, "no such console: {}", index);
+
+    let active = active_console();
+    if index == active {
+        return;
+    }
+
+    // always lock the lower index first, so two switches can't deadlock
+    let (first, second) = (active.min(index), active.max(index));
+    let first = CONSOLES[first].lock();
+    let second = CONSOLES[second].lock();
+    let (mut old, mut new) =
+        if active < index { (first, second) } else { (second, first) };
+
+    old.return_to_live();
+    for row in 0..BUFFER_HEIGHT {
+        for col in 0..BUFFER_WIDTH {
+            let shown = old.buffer.chars[row][col].read();
+            let hidden = new.buffer.chars[row][col].read();
+            old.buffer.chars[row][col].write(hidden);
+            new.buffer.chars[row][col].write(shown);
+        }
+    }
+    core::mem::swap(&mut old.buffer, &mut new.buffer);
+
+    old.cursor.active = false;
+    new.cursor.active = true;
+    new.cursor.update_shape();
+    new.update_cursor();
+
+    ACTIVE.store(index, Ordering::Relaxed);
+}
+
+
+#[test_case]
+fn test_switch_console() {
+    use core::fmt::Write;
+
+    let screen = &*console(0).lock().buffer as *const Buffer;
+    let row = BUFFER_HEIGHT - 1;
+    write!(console(1).lock(), "\nconsole 1").unwrap();
+
+    switch_console(1);
+    assert_eq!(active_console(), 1);
+    {
+        let writer = console(1).lock();
+        assert!(core::ptr::eq(writer.buffer, screen));
+        assert!(writer.cursor.active);
+        assert_eq!(writer.buffer.chars[row][8].read().ascii_character, b'1');
+    }
+
+    switch_console(0);
+    assert_eq!(active_console(), 0);
+    assert!(core::ptr::eq(console(0).lock().buffer, screen));
+    let writer = console(1).lock();
+    assert!(!core::ptr::eq(writer.buffer, screen));
+    assert_eq!(writer.buffer.chars[row][8].read().ascii_character, b'1');
+}
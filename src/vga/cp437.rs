@@ -0,0 +1,75 @@
+//! Mapping from Unicode to the glyphs of code page 437, the character set
+//! built into VGA text mode.
+
+/// The glyph shown for characters that code page 437 doesn't have.
+pub const REPLACEMENT: u8 = 0xfe;
+
+/// The characters drawn for bytes 0x01 to 0x1f, which are control characters
+/// in ASCII.
+const LOW: [char; 31] = [
+    '☺', '☻', '♥', '♦', '♣', '♠', '•', '◘', '○', '◙', '♂', '♀', '♪', '♫', '☼',
+    '►', '◄', '↕', '‼', '¶', '§', '▬', '↨', '↑', '↓', '→', '←', '∟', '↔', '▲',
+    '▼',
+];
+
+/// The characters drawn for bytes 0x80 to 0xff.
+const HIGH: [char; 128] = [
+    'Ç', 'ü', 'é', 'â', 'ä', 'à', 'å', 'ç', 'ê', 'ë', 'è', 'ï', 'î', 'ì', 'Ä',
+    'Å', 'É', 'æ', 'Æ', 'ô', 'ö', 'ò', 'û', 'ù', 'ÿ', 'Ö', 'Ü', '¢', '£', '¥',
+    '₧', 'ƒ', 'á', 'í', 'ó', 'ú', 'ñ', 'Ñ', 'ª', 'º', '¿', '⌐', '¬', '½', '¼',
+    '¡', '«', '»', '░', '▒', '▓', '│', '┤', '╡', '╢', '╖', '╕', '╣', '║', '╗',
+    '╝', '╜', '╛', '┐', '└', '┴', '┬', '├', '─', '┼', '╞', '╟', '╚', '╔', '╩',
+    '╦', '╠', '═', '╬', '╧', '╨', '╤', '╥', '╙', '╘', '╒', '╓', '╫', '╪', '┘',
+    '┌', '█', '▄', '▌', '▐', '▀', 'α', 'ß', 'Γ', 'π', 'Σ', 'σ', 'µ', 'τ', 'Φ',
+    'Θ', 'Ω', 'δ', '∞', 'φ', 'ε', '∩', '≡', '±', '≥', '≤', '⌠', '⌡', '÷', '≈',
+    '°', '∙', '·', '√', 'ⁿ', '²', '■', '\u{a0}',
+];
+
+/// Characters that look close enough to one of the glyphs to share it.
+const ALIASES: [(char, u8); 6] = [
+    ('β', 0xe1),
+    ('μ', 0xe6),
+    ('∑', 0xe4),
+    ('∈', 0xee),
+    ('\u{2126}', 0xea), // the ohm sign
+    ('⌂', 0x7f),
+];
+
+/// Returns the code page 437 byte that draws the given character, if there is
+/// one.
+///
+/// Printable ASCII maps to itself. ASCII control characters don't map to
+/// anything, even though their bytes have glyphs, as they're never meant to be
+/// drawn.
+pub fn from_char(c: char) -> Option<u8> {
+    if (' '..='~').contains(&c) {
+        return Some(c as u8);
+    }
+
+    let position = |table: &[char]| table.iter().position(|&glyph| glyph == c);
+    if let Some(index) = position(&HIGH) {
+        return Some(0x80 + index as u8);
+    }
+    if let Some(index) = position(&LOW) {
+        return Some(0x01 + index as u8);
+    }
+    ALIASES.iter().find(|&&(alias, _)| alias == c).map(|&(_, byte)| byte)
+}
+
+/// Like `from_char`, but gives `REPLACEMENT` for characters that can't be
+/// drawn.
+pub fn from_char_or_replacement(c: char) -> u8 {
+    from_char(c).unwrap_or(REPLACEMENT)
+}
+
+
+#[test_case]
+fn test_from_char() {
+    assert_eq!(from_char('A'), Some(b'A'));
+    assert_eq!(from_char('é'), Some(0x82));
+    assert_eq!(from_char('╔'), Some(0xc9));
+    assert_eq!(from_char('☺'), Some(0x01));
+    assert_eq!(from_char('\u{a0}'), Some(0xff));
+    assert_eq!(from_char('\n'), None);
+    assert_eq!(from_char_or_replacement('€'), REPLACEMENT);
+}
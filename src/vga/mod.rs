@@ -0,0 +1,752 @@
+mod ansi;
+mod console;
+pub mod cp437;
+mod scrollback;
+pub mod tui;
+
+pub const BUFFER_HEIGHT: usize = 25;
+pub const BUFFER_WIDTH: usize = 80;
+/// The number of rows kept once the scrollback is turned on by `init`.
+pub const DEFAULT_SCROLLBACK_LEN: usize = 100;
+
+use core::fmt;
+
+use lazy_static::lazy_static;
+use volatile::Volatile;
+use x86_64::instructions::port::{Port, PortWriteOnly};
+
+use self::ansi::{Action, Csi};
+pub use self::console::{
+    active_console, console, detach_screen, switch_console, with_screen,
+    CONSOLE_COUNT,
+};
+use self::scrollback::Scrollback;
+use crate::console::Sink;
+use crate::sync::IrqSafeMutex;
+
+/// The VGA colours matching the 8 standard ANSI colours, in ANSI order. Adding
+/// 8 to any of these gives the bright version.
+const ANSI_COLORS: [Color; 8] = [
+    Color::Black,
+    Color::Red,
+    Color::Green,
+    Color::Brown,
+    Color::Blue,
+    Color::Magenta,
+    Color::Cyan,
+    Color::LightGray,
+];
+
+lazy_static! {
+    /// The first console, which `print!` and `println!` write to.
+    pub static ref VGA_WRITER: &'static IrqSafeMutex<VGAWriter> = console(0);
+}
+
+/// Writes text to the VGA text buffer, or to one of the off-screen buffers if
+/// it belongs to a console that isn't being shown.
+///
+/// Rows that scroll off the top of the screen are kept in a scrollback
+/// history, which can be looked through with `scroll_up` and `scroll_down`.
+/// Writing anything goes back to showing live output.
+///
+/// Strings may contain a subset of the ANSI/VT100 escape sequences: colours
+/// and bold (`SGR`), cursor movement, saving and restoring the cursor,
+/// clearing the screen or line, and showing or hiding the cursor.
+pub struct VGAWriter {
+    row_position:    usize,
+    column_position: usize,
+    color_code:      ColorCode,
+    /// The colours that `ESC [ 0 m` goes back to.
+    base_color:      ColorCode,
+    /// Whether bright foreground colours were asked for with `ESC [ 1 m`.
+    bold:            bool,
+    saved_position:  (usize, usize),
+    ansi:            ansi::Parser,
+    /// Only there once the heap is available.
+    scrollback:      Option<Scrollback>,
+    cursor:          Cursor,
+    buffer:          &'static mut Buffer,
+}
+
+impl VGAWriter {
+    /// Writes a single byte as it is, which is drawn with its code page 437
+    /// glyph. Escape sequences aren't interpreted.
+    pub fn write_byte(&mut self, byte: u8) {
+        self.return_to_live();
+        self.put_byte(byte);
+        self.update_cursor();
+    }
+
+    /// Writes a string, drawing each character with its code page 437 glyph,
+    /// or `cp437::REPLACEMENT` if it doesn't have one.
+    pub fn write_str(&mut self, s: &str) {
+        self.return_to_live();
+        for c in s.chars() {
+            match self.ansi.advance(c) {
+                Action::None => {},
+                Action::Csi(csi) => self.apply(csi),
+                Action::Print('\n') => self.new_line(),
+                Action::Print('\r') => self.column_position = 0,
+                Action::Print(c) =>
+                    self.put_byte(cp437::from_char_or_replacement(c)),
+            }
+        }
+        self.update_cursor();
+    }
+
+    /// Carries out a control sequence.
+    fn apply(&mut self, csi: Csi) {
+        let n = |index| usize::from(csi.param_or(index, 1));
+        let (row, col) = (self.row_position, self.column_position);
+
+        match (csi.private, csi.command) {
+            (false, 'm') => self.select_graphic_rendition(csi.params()),
+            (false, 'A') => self.set_position(row.saturating_sub(n(0)), col),
+            (false, 'B') => self.set_position(row + n(0), col),
+            (false, 'C') =>
+                self.set_position(row, (col + n(0)).min(BUFFER_WIDTH - 1)),
+            (false, 'D') => self.set_position(row, col.saturating_sub(n(0))),
+            (false, 'G') => self.set_position(row, n(0) - 1),
+            (false, 'H') | (false, 'f') =>
+                self.set_position(n(0) - 1, (n(1) - 1).min(BUFFER_WIDTH - 1)),
+            (false, 'J') => {
+                let cursor = row * BUFFER_WIDTH + col.min(BUFFER_WIDTH - 1);
+                match csi.param_or(0, 0) {
+                    0 => self.erase(cursor, BUFFER_HEIGHT * BUFFER_WIDTH),
+                    1 => self.erase(0, cursor + 1),
+                    _ => self.erase(0, BUFFER_HEIGHT * BUFFER_WIDTH),
+                }
+            },
+            (false, 'K') => {
+                let start = row * BUFFER_WIDTH;
+                let cursor = start + col.min(BUFFER_WIDTH - 1);
+                match csi.param_or(0, 0) {
+                    0 => self.erase(cursor, start + BUFFER_WIDTH),
+                    1 => self.erase(start, cursor + 1),
+                    _ => self.erase(start, start + BUFFER_WIDTH),
+                }
+            },
+            (false, 's') => self.saved_position = (row, col),
+            (false, 'u') => {
+                let (row, col) = self.saved_position;
+                self.set_position(row, col);
+            },
+            (true, 'h') | (true, 'l') if csi.params() == [25] =>
+                self.set_cursor_visible(csi.command == 'h'),
+            _ => {},
+        }
+    }
+
+    /// Handles an `ESC [ ... m` sequence, which changes the colours.
+    fn select_graphic_rendition(&mut self, params: &[u16]) {
+        // with no parameters, it's the same as a reset
+        let params: &[u16] = if params.is_empty() { &[0] } else { params };
+
+        for &param in params {
+            let ColorCode(code) = self.color_code;
+            let ColorCode(base) = self.base_color;
+            let (mut fg, mut bg) = (code & 0x0f, code >> 4);
+            let bright = if self.bold { 8 } else { 0 };
+
+            match param {
+                0 => {
+                    self.bold = false;
+                    fg = base & 0x0f;
+                    bg = base >> 4;
+                },
+                1 => {
+                    self.bold = true;
+                    fg |= 8;
+                },
+                22 => {
+                    self.bold = false;
+                    fg &= !8;
+                },
+                30..=37 =>
+                    fg = ANSI_COLORS[usize::from(param - 30)] as u8 | bright,
+                39 => fg = base & 0x0f | bright,
+                40..=47 => bg = ANSI_COLORS[usize::from(param - 40)] as u8,
+                49 => bg = base >> 4,
+                90..=97 => fg = ANSI_COLORS[usize::from(param - 90)] as u8 | 8,
+                100..=107 =>
+                    bg = ANSI_COLORS[usize::from(param - 100)] as u8 | 8,
+                _ => {},
+            }
+            self.color_code = ColorCode(bg << 4 | fg);
+        }
+    }
+
+    /// Blanks the characters from `start` up to (but not including) `end`,
+    /// counting across each row and then down.
+    fn erase(&mut self, start: usize, end: usize) {
+        let blank =
+            VGAChar { ascii_character: b' ', color_code: self.color_code };
+        for position in start..end {
+            let (row, col) = (position / BUFFER_WIDTH, position % BUFFER_WIDTH);
+            self.buffer.chars[row][col].write(blank);
+        }
+    }
+
+    /// Writes a string starting at the given position, without moving the
+    /// cursor. Anything past the end of the row, or off the screen, is cut
+    /// off.
+    pub fn write_str_at(&mut self, row: usize, col: usize, s: &str) {
+        if row >= BUFFER_HEIGHT {
+            return;
+        }
+        self.return_to_live();
+        let color_code = self.color_code;
+        for (col, c) in (col..BUFFER_WIDTH).zip(s.chars()) {
+            let byte = cp437::from_char_or_replacement(c);
+            self.buffer.chars[row][col]
+                .write(VGAChar { ascii_character: byte, color_code });
+        }
+    }
+
+    /// Writes a byte from code page 437 at the given position, without moving
+    /// the cursor. Nothing is written if the position is off the screen.
+    pub fn write_byte_at(&mut self, row: usize, col: usize, byte: u8) {
+        if row >= BUFFER_HEIGHT || col >= BUFFER_WIDTH {
+            return;
+        }
+        self.return_to_live();
+        let color_code = self.color_code;
+        self.buffer.chars[row][col]
+            .write(VGAChar { ascii_character: byte, color_code });
+    }
+
+    fn put_byte(&mut self, byte: u8) {
+        match byte {
+            b'\n' => self.new_line(),
+            byte => {
+                if self.column_position >= BUFFER_WIDTH {
+                    self.new_line();
+                }
+
+                let row = self.row_position;
+                let col = self.column_position;
+                let color_code = self.color_code;
+
+                self.buffer.chars[row][col]
+                    .write(VGAChar { ascii_character: byte, color_code });
+                self.column_position += 1;
+            },
+        }
+    }
+
+    /// Moves to the start of the next row, scrolling everything up if this is
+    /// the last one.
+    fn new_line(&mut self) {
+        self.column_position = 0;
+        if self.row_position < BUFFER_HEIGHT - 1 {
+            self.row_position += 1;
+            return;
+        }
+
+        if let Some(scrollback) = &mut self.scrollback {
+            scrollback.push(scrollback::read_row(self.buffer, 0));
+        }
+
+        for row in 1..BUFFER_HEIGHT {
+            for col in 0..BUFFER_WIDTH {
+                let character = self.buffer.chars[row][col].read();
+                self.buffer.chars[row - 1][col].write(character);
+            }
+        }
+        self.clear_row(BUFFER_HEIGHT - 1);
+    }
+
+    /// Returns the column that the next character will be written to.
+    pub fn column(&self) -> usize {
+        self.column_position
+    }
+
+    /// Moves the cursor to the given column of the current line.
+    pub fn set_column(&mut self, column: usize) {
+        self.column_position = column.min(BUFFER_WIDTH);
+        self.update_cursor();
+    }
+
+    /// Returns the row that the next character will be written to.
+    pub fn row(&self) -> usize {
+        self.row_position
+    }
+
+    /// Moves the cursor to the given row and column.
+    pub fn set_position(&mut self, row: usize, column: usize) {
+        self.row_position = row.min(BUFFER_HEIGHT - 1);
+        self.set_column(column);
+    }
+
+    /// Returns the character at the given position, and its colours.
+    pub fn char_at(&self, row: usize, col: usize) -> ScreenChar {
+        let VGAChar { ascii_character, color_code: ColorCode(code) } =
+            self.buffer.chars[row][col].read();
+        ScreenChar {
+            byte: ascii_character,
+            fg:   Color::ALL[code as usize & 0xf],
+            bg:   Color::ALL[code as usize >> 4],
+        }
+    }
+
+    /// Swaps the foreground and background colours of the character at the
+    /// given position, e.g. to highlight it. Doing this twice undoes it.
+    pub fn invert_colors(&mut self, row: usize, col: usize) {
+        let mut character = self.buffer.chars[row][col].read();
+        let ColorCode(code) = character.color_code;
+        character.color_code = ColorCode(code.rotate_left(4));
+        self.buffer.chars[row][col].write(character);
+    }
+
+    /// Blanks the whole screen, and moves the cursor to the top-left corner.
+    pub fn clear(&mut self) {
+        self.return_to_live();
+        for row in 0..BUFFER_HEIGHT {
+            self.clear_row(row);
+        }
+        self.set_position(0, 0);
+    }
+
+    /// Changes the colours used for anything written from now on, including
+    /// after an `ESC [ 0 m`.
+    pub fn set_color(&mut self, fg: Color, bg: Color) {
+        self.color_code = ColorCode::new(fg, bg);
+        self.base_color = self.color_code;
+        self.bold = false;
+    }
+
+    /// Sets the number of rows kept in the scrollback history, clearing it.
+    /// Needs the heap, unless `len` is 0, which turns the scrollback off.
+    pub fn set_scrollback_len(&mut self, len: usize) {
+        self.return_to_live();
+        self.scrollback = match len {
+            0 => None,
+            len => Some(Scrollback::new(len)),
+        };
+    }
+
+    /// Shows `rows` rows further back in the scrollback history.
+    pub fn scroll_up(&mut self, rows: usize) {
+        if let Some(scrollback) = &mut self.scrollback {
+            scrollback.scroll_up(self.buffer, rows);
+        }
+    }
+
+    /// Shows `rows` rows closer to live output.
+    pub fn scroll_down(&mut self, rows: usize) {
+        if let Some(scrollback) = &mut self.scrollback {
+            scrollback.scroll_down(self.buffer, rows);
+        }
+    }
+
+    /// Returns true if the screen is showing the scrollback history rather
+    /// than live output.
+    pub fn is_scrolled_back(&self) -> bool {
+        self.scrollback.as_ref().map_or(false, Scrollback::is_viewing)
+    }
+
+    /// Goes back to showing live output, if the history is being shown.
+    pub fn return_to_live(&mut self) {
+        if let Some(scrollback) = &mut self.scrollback {
+            if scrollback.is_viewing() {
+                scrollback.return_to_live(self.buffer);
+            }
+        }
+    }
+
+    /// Shows or hides the blinking hardware cursor.
+    pub fn set_cursor_visible(&mut self, visible: bool) {
+        self.cursor.visible = visible;
+        self.cursor.update_shape();
+    }
+
+    pub fn cursor_visible(&self) -> bool {
+        self.cursor.visible
+    }
+
+    /// Changes the hardware cursor's shape.
+    pub fn set_cursor_shape(&mut self, shape: CursorShape) {
+        self.cursor.shape = shape;
+        self.cursor.update_shape();
+    }
+
+    fn update_cursor(&mut self) {
+        // the cursor sits on the last column while a row is full, rather than
+        // wrapping early
+        let col = self.column_position.min(BUFFER_WIDTH - 1);
+        self.cursor.move_to(self.row_position, col);
+    }
+
+    fn clear_row(&mut self, row: usize) {
+        let blank =
+            VGAChar { ascii_character: b' ', color_code: self.color_code };
+        for col in 0..BUFFER_WIDTH {
+            self.buffer.chars[row][col].write(blank);
+        }
+    }
+}
+
+/// The scanlines of a character cell that the hardware cursor covers, from 0
+/// at the top to 15 at the bottom.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CursorShape {
+    pub start: u8,
+    pub end:   u8,
+}
+
+impl CursorShape {
+    pub const UNDERLINE: CursorShape = CursorShape { start: 14, end: 15 };
+    pub const BLOCK: CursorShape = CursorShape { start: 0, end: 15 };
+}
+
+/// The hardware cursor, which is drawn by the VGA controller and controlled
+/// through its CRT controller registers.
+struct Cursor {
+    index:   PortWriteOnly<u8>,
+    data:    Port<u8>,
+    /// Whether this cursor's console is the one on the screen. Only that
+    /// console gets to move the hardware cursor.
+    active:  bool,
+    visible: bool,
+    shape:   CursorShape,
+}
+
+impl Cursor {
+    const CURSOR_START: u8 = 0x0a;
+    const CURSOR_END: u8 = 0x0b;
+    const LOCATION_HIGH: u8 = 0x0e;
+    const LOCATION_LOW: u8 = 0x0f;
+    /// Set in the cursor start register to hide the cursor.
+    const DISABLE: u8 = 1 << 5;
+
+    const fn new() -> Self {
+        Cursor {
+            index:   PortWriteOnly::new(0x3d4),
+            data:    Port::new(0x3d5),
+            active:  true,
+            visible: true,
+            shape:   CursorShape::UNDERLINE,
+        }
+    }
+
+    fn move_to(&mut self, row: usize, col: usize) {
+        if !self.active {
+            return;
+        }
+        let location = (row * BUFFER_WIDTH + col) as u16;
+        self.write(Self::LOCATION_HIGH, (location >> 8) as u8);
+        self.write(Self::LOCATION_LOW, location as u8);
+    }
+
+    fn update_shape(&mut self) {
+        if !self.active {
+            return;
+        }
+        let disable = if self.visible { 0 } else { Self::DISABLE };
+        let start = self.read(Self::CURSOR_START) & 0xc0;
+        self.write(
+            Self::CURSOR_START,
+            start | disable | self.shape.start & 0x1f,
+        );
+        let end = self.read(Self::CURSOR_END) & 0xe0;
+        self.write(Self::CURSOR_END, end | self.shape.end & 0x1f);
+    }
+
+    fn read(&mut self, register: u8) -> u8 {
+        unsafe {
+            self.index.write(register);
+            self.data.read()
+        }
+    }
+
+    fn write(&mut self, register: u8, value: u8) {
+        unsafe {
+            self.index.write(register);
+            self.data.write(value);
+        }
+    }
+}
+
+/// Turns on the scrollback history of every console. Has to wait until the
+/// heap is set up.
+pub fn init() {
+    for index in 0..CONSOLE_COUNT {
+        console(index).lock().set_scrollback_len(DEFAULT_SCROLLBACK_LEN);
+    }
+}
+
+pub fn with_color<F: Fn()>(fg: Color, bg: Color, task: F) {
+    let mut writer = VGA_WRITER.lock();
+    let old_colors = (writer.color_code, writer.base_color, writer.bold);
+    writer.set_color(fg, bg);
+    drop(writer);
+
+    task();
+
+    let mut writer = VGA_WRITER.lock();
+    let (color_code, base_color, bold) = old_colors;
+    writer.color_code = color_code;
+    writer.base_color = base_color;
+    writer.bold = bold;
+}
+
+impl Default for VGAWriter {
+    fn default() -> Self {
+        let white_on_black = ColorCode::new(Color::White, Color::Black);
+        Self {
+            row_position:    BUFFER_HEIGHT - 1,
+            column_position: 0,
+            color_code:      white_on_black,
+            base_color:      white_on_black,
+            bold:            false,
+            saved_position:  (0, 0),
+            ansi:            ansi::Parser::new(),
+            scrollback:      None,
+            cursor:          Cursor::new(),
+            buffer:          unsafe { &mut *(0xb8000 as *mut Buffer) },
+        }
+    }
+}
+
+impl fmt::Write for VGAWriter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.write_str(s);
+        Ok(())
+    }
+}
+
+/// A character on the screen, as its code page 437 byte, and its colours.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScreenChar {
+    pub byte: u8,
+    pub fg:   Color,
+    pub bg:   Color,
+}
+
+#[repr(transparent)]
+struct Buffer {
+    chars: [[Volatile<VGAChar>; BUFFER_WIDTH]; BUFFER_HEIGHT],
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct VGAChar {
+    ascii_character: u8,
+    color_code:      ColorCode,
+}
+
+impl VGAChar {
+    /// A space, in white on black.
+    const BLANK: VGAChar =
+        VGAChar { ascii_character: b' ', color_code: ColorCode(0x0f) };
+}
+
+#[repr(transparent)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct ColorCode(u8);
+
+impl ColorCode {
+    fn new(foreground: Color, background: Color) -> ColorCode {
+        ColorCode((background as u8) << 4 | (foreground as u8))
+    }
+}
+
+#[repr(u8)]
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Color {
+    Black      = 0,
+    Blue       = 1,
+    Green      = 2,
+    Cyan       = 3,
+    Red        = 4,
+    Magenta    = 5,
+    Brown      = 6,
+    LightGray  = 7,
+    DarkGray   = 8,
+    LightBlue  = 9,
+    LightGreen = 10,
+    LightCyan  = 11,
+    LightRed   = 12,
+    Pink       = 13,
+    Yellow     = 14,
+    White      = 15,
+}
+
+impl Color {
+    pub const ALL: [Color; 16] = [
+        Color::Black,
+        Color::Blue,
+        Color::Green,
+        Color::Cyan,
+        Color::Red,
+        Color::Magenta,
+        Color::Brown,
+        Color::LightGray,
+        Color::DarkGray,
+        Color::LightBlue,
+        Color::LightGreen,
+        Color::LightCyan,
+        Color::LightRed,
+        Color::Pink,
+        Color::Yellow,
+        Color::White,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Color::Black => "black",
+            Color::Blue => "blue",
+            Color::Green => "green",
+            Color::Cyan => "cyan",
+            Color::Red => "red",
+            Color::Magenta => "magenta",
+            Color::Brown => "brown",
+            Color::LightGray => "lightgray",
+            Color::DarkGray => "darkgray",
+            Color::LightBlue => "lightblue",
+            Color::LightGreen => "lightgreen",
+            Color::LightCyan => "lightcyan",
+            Color::LightRed => "lightred",
+            Color::Pink => "pink",
+            Color::Yellow => "yellow",
+            Color::White => "white",
+        }
+    }
+
+    /// Looks up a colour by its `name`, ignoring case.
+    pub fn from_name(name: &str) -> Option<Color> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|color| color.name().eq_ignore_ascii_case(name))
+    }
+}
+
+/// Sends console output to the first VGA console, which is also drawn on the
+/// framebuffer if that's being used.
+pub struct VgaSink;
+
+impl Sink for VgaSink {
+    fn name(&self) -> &'static str {
+        "vga"
+    }
+
+    fn write_str(&self, s: &str) {
+        VGA_WRITER.lock().write_str(s);
+    }
+
+    fn flush(&self) {
+        crate::framebuffer::refresh();
+    }
+}
+
+#[test_case]
+fn test_println() {
+    crate::println!("test_println_simple output");
+}
+
+#[test_case]
+fn test_println_many() {
+    for _ in 0..200 {
+        crate::println!("test_println_many output");
+    }
+}
+
+#[test_case]
+fn test_println_output() {
+    use core::fmt::Write;
+    let s = "Some test string that fits on a single line";
+
+    let mut writer = VGA_WRITER.lock();
+    writeln!(writer, "\n{}", s).expect("writeln failed");
+    for (i, c) in s.chars().enumerate() {
+        let screen_char = writer.buffer.chars[BUFFER_HEIGHT - 2][i].read();
+        assert_eq!(char::from(screen_char.ascii_character), c);
+    }
+}
+
+#[test_case]
+fn test_positioning() {
+    let mut writer = VGA_WRITER.lock();
+    let (row, column) = (writer.row(), writer.column());
+
+    writer.write_str_at(3, BUFFER_WIDTH - 2, "abc");
+    assert_eq!(
+        writer.buffer.chars[3][BUFFER_WIDTH - 1].read().ascii_character,
+        b'b'
+    );
+    assert_eq!((writer.row(), writer.column()), (row, column));
+
+    writer.set_position(5, 10);
+    writer.write_str("xy\nz");
+    assert_eq!(writer.buffer.chars[5][11].read().ascii_character, b'y');
+    assert_eq!(writer.buffer.chars[6][0].read().ascii_character, b'z');
+    assert_eq!((writer.row(), writer.column()), (6, 1));
+
+    writer.set_position(BUFFER_HEIGHT + 10, BUFFER_WIDTH + 10);
+    assert_eq!(
+        (writer.row(), writer.column()),
+        (BUFFER_HEIGHT - 1, BUFFER_WIDTH)
+    );
+}
+
+#[test_case]
+fn test_positioned_writes_off_screen() {
+    let mut writer = VGA_WRITER.lock();
+    let first = scrollback::read_row(writer.buffer, 0);
+    let last = scrollback::read_row(writer.buffer, BUFFER_HEIGHT - 1);
+
+    // these are cut off rather than panicking
+    writer.write_str_at(BUFFER_HEIGHT, 0, "x");
+    writer.write_str_at(0, BUFFER_WIDTH, "x");
+    writer.write_byte_at(BUFFER_HEIGHT, 0, b'x');
+    writer.write_byte_at(BUFFER_HEIGHT - 1, BUFFER_WIDTH, b'x');
+    assert!(scrollback::read_row(writer.buffer, 0) == first);
+    assert!(scrollback::read_row(writer.buffer, BUFFER_HEIGHT - 1) == last);
+}
+
+#[test_case]
+fn test_ansi_escapes() {
+    let mut writer = VGA_WRITER.lock();
+    let base_color = writer.base_color;
+
+    writer.write_str("\x1b[2;3H\x1b[1;34mA\x1b[0mB\x1b[K\x1b[5Dx");
+    let a = writer.buffer.chars[1][2].read();
+    let b = writer.buffer.chars[1][3].read();
+    assert_eq!(a.ascii_character, b'A');
+    assert_eq!(a.color_code, ColorCode::new(Color::LightBlue, Color::Black));
+    assert_eq!((b.ascii_character, b.color_code), (b'B', base_color));
+    assert_eq!(writer.buffer.chars[1][4].read().ascii_character, b' ');
+    assert_eq!(writer.buffer.chars[1][0].read().ascii_character, b'x');
+
+    writer.set_position(BUFFER_HEIGHT - 1, 0);
+}
+
+#[test_case]
+fn test_scrollback() {
+    let mut writer = VGA_WRITER.lock();
+    writer.set_scrollback_len(BUFFER_HEIGHT);
+    writer.clear();
+    for i in 0..BUFFER_HEIGHT * 2 {
+        writer.write_str(&alloc::format!("\nline {}", i));
+    }
+    let live = scrollback::read_row(writer.buffer, 0);
+
+    // the first line written was pushed off the top, so it's the oldest
+    writer.scroll_up(BUFFER_HEIGHT * 3);
+    assert!(writer.is_scrolled_back());
+    assert_eq!(writer.buffer.chars[0][5].read().ascii_character, b'0');
+
+    writer.scroll_down(BUFFER_HEIGHT);
+    assert!(!writer.is_scrolled_back());
+    assert!(scrollback::read_row(writer.buffer, 0) == live);
+
+    // writing anything goes back to live output
+    writer.scroll_up(1);
+    writer.write_str("!");
+    assert!(!writer.is_scrolled_back());
+
+    writer.set_scrollback_len(DEFAULT_SCROLLBACK_LEN);
+    writer.set_position(BUFFER_HEIGHT - 1, 0);
+}
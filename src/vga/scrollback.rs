@@ -0,0 +1,103 @@
+use alloc::boxed::Box;
+use alloc::collections::VecDeque;
+
+use super::{Buffer, VGAChar, BUFFER_HEIGHT, BUFFER_WIDTH};
+
+type Row = [VGAChar; BUFFER_WIDTH];
+
+/// Rows that have scrolled off the top of the screen, and which of them (if
+/// any) are being looked at.
+pub(super) struct Scrollback {
+    /// The oldest row is at the front.
+    rows:   VecDeque<Row>,
+    len:    usize,
+    /// How many rows back from live output the screen is showing.
+    offset: usize,
+    /// The live screen, saved while the screen is showing the history.
+    live:   Option<Box<[Row; BUFFER_HEIGHT]>>,
+}
+
+impl Scrollback {
+    /// Creates a history that keeps up to `len` rows.
+    pub(super) fn new(len: usize) -> Self {
+        Scrollback {
+            rows: VecDeque::with_capacity(len),
+            len,
+            offset: 0,
+            live: None,
+        }
+    }
+
+    pub(super) fn is_viewing(&self) -> bool {
+        self.offset > 0
+    }
+
+    /// Records a row that's about to scroll off the screen.
+    pub(super) fn push(&mut self, row: Row) {
+        if self.rows.len() == self.len {
+            self.rows.pop_front();
+        }
+        self.rows.push_back(row);
+    }
+
+    /// Moves the view `rows` further back into the history.
+    pub(super) fn scroll_up(&mut self, buffer: &mut Buffer, rows: usize) {
+        if self.offset == 0 {
+            let mut live =
+                Box::new([[VGAChar::BLANK; BUFFER_WIDTH]; BUFFER_HEIGHT]);
+            for (row, saved) in live.iter_mut().enumerate() {
+                *saved = read_row(buffer, row);
+            }
+            self.live = Some(live);
+        }
+        self.offset = (self.offset + rows).min(self.rows.len());
+        self.draw(buffer);
+    }
+
+    /// Moves the view `rows` closer to live output.
+    pub(super) fn scroll_down(&mut self, buffer: &mut Buffer, rows: usize) {
+        self.offset = self.offset.saturating_sub(rows);
+        self.draw(buffer);
+    }
+
+    /// Puts the live screen back, if the history is being shown.
+    pub(super) fn return_to_live(&mut self, buffer: &mut Buffer) {
+        self.offset = 0;
+        self.draw(buffer);
+    }
+
+    fn draw(&mut self, buffer: &mut Buffer) {
+        if self.offset == 0 {
+            if let Some(live) = self.live.take() {
+                for (row, chars) in live.iter().enumerate() {
+                    write_row(buffer, row, chars);
+                }
+            }
+            return;
+        }
+
+        let live = self.live.as_ref().expect("live screen wasn't saved");
+        let top = self.rows.len() - self.offset;
+        for row in 0..BUFFER_HEIGHT {
+            let chars = match self.rows.get(top + row) {
+                Some(chars) => chars,
+                None => &live[top + row - self.rows.len()],
+            };
+            write_row(buffer, row, chars);
+        }
+    }
+}
+
+pub(super) fn read_row(buffer: &Buffer, row: usize) -> Row {
+    let mut chars = [VGAChar::BLANK; BUFFER_WIDTH];
+    for (col, c) in chars.iter_mut().enumerate() {
+        *c = buffer.chars[row][col].read();
+    }
+    chars
+}
+
+fn write_row(buffer: &mut Buffer, row: usize, chars: &Row) {
+    for (col, &c) in chars.iter().enumerate() {
+        buffer.chars[row][col].write(c);
+    }
+}
@@ -0,0 +1,372 @@
+//! A simple text user interface drawn over the VGA text buffer: bordered
+//! windows, status bars, and regions of text that scroll on their own.
+//!
+//! Widgets are drawn onto a `VGAWriter`, so they can be put on any console.
+//! Nothing is redrawn by itself, so call `draw` again after changing a widget.
+//! Anything that would be drawn off the edge of the screen is cut off.
+
+use alloc::collections::VecDeque;
+use alloc::string::String;
+use core::fmt;
+
+use super::{
+    cp437, Color, ColorCode, VGAChar, VGAWriter, BUFFER_HEIGHT, BUFFER_WIDTH,
+};
+
+/// The colours to draw something in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Style {
+    pub fg: Color,
+    pub bg: Color,
+}
+
+impl Style {
+    pub const NORMAL: Style = Style::new(Color::White, Color::Black);
+    pub const INVERTED: Style = Style::new(Color::Black, Color::LightGray);
+
+    pub const fn new(fg: Color, bg: Color) -> Self {
+        Style { fg, bg }
+    }
+}
+
+/// A rectangle of character cells, with its top-left corner at `row` and
+/// `col`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rect {
+    pub row:    usize,
+    pub col:    usize,
+    pub height: usize,
+    pub width:  usize,
+}
+
+impl Rect {
+    /// The whole screen.
+    pub const SCREEN: Rect = Rect::new(0, 0, BUFFER_HEIGHT, BUFFER_WIDTH);
+
+    pub const fn new(
+        row: usize, col: usize, height: usize, width: usize,
+    ) -> Self {
+        Rect { row, col, height, width }
+    }
+
+    /// Returns the part of the rectangle inside a border one cell wide.
+    pub fn inner(self) -> Rect {
+        Rect {
+            row:    self.row + 1,
+            col:    self.col + 1,
+            height: self.height.saturating_sub(2),
+            width:  self.width.saturating_sub(2),
+        }
+    }
+
+    /// Splits the rectangle into its first `rows` rows and the rest.
+    pub fn split_rows(self, rows: usize) -> (Rect, Rect) {
+        let rows = rows.min(self.height);
+        let top = Rect { height: rows, ..self };
+        let bottom =
+            Rect { row: self.row + rows, height: self.height - rows, ..self };
+        (top, bottom)
+    }
+
+    /// Splits the rectangle into its first `cols` columns and the rest.
+    pub fn split_cols(self, cols: usize) -> (Rect, Rect) {
+        let cols = cols.min(self.width);
+        let left = Rect { width: cols, ..self };
+        let right =
+            Rect { col: self.col + cols, width: self.width - cols, ..self };
+        (left, right)
+    }
+
+    fn is_empty(self) -> bool {
+        self.height == 0 || self.width == 0
+    }
+
+    fn last_row(self) -> usize {
+        self.row + self.height - 1
+    }
+
+    fn last_col(self) -> usize {
+        self.col + self.width - 1
+    }
+}
+
+/// The lines used to draw a window's border.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Border {
+    Single,
+    Double,
+}
+
+impl Border {
+    /// Returns the lines going across and down, then the top-left,
+    /// top-right, bottom-left and bottom-right corners.
+    fn chars(self) -> [char; 6] {
+        match self {
+            Border::Single => ['─', '│', '┌', '┐', '└', '┘'],
+            Border::Double => ['═', '║', '╔', '╗', '╚', '╝'],
+        }
+    }
+
+    /// Returns the characters that go either side of a title, joining it to
+    /// the top of the border.
+    fn title_ends(self) -> (char, char) {
+        match self {
+            Border::Single => ('┤', '├'),
+            Border::Double => ('╡', '╞'),
+        }
+    }
+}
+
+/// A bordered rectangle, with a title along the top.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Window {
+    pub rect:         Rect,
+    pub title:        String,
+    pub border:       Border,
+    /// The colours of the border and title.
+    pub border_style: Style,
+    /// The colours of the inside of the window.
+    pub style:        Style,
+}
+
+impl Window {
+    pub fn new(rect: Rect, title: &str) -> Self {
+        Window {
+            rect,
+            title: String::from(title),
+            border: Border::Single,
+            border_style: Style::new(Color::LightCyan, Color::Black),
+            style: Style::NORMAL,
+        }
+    }
+
+    /// Returns the part of the window inside its border.
+    pub fn content(&self) -> Rect {
+        self.rect.inner()
+    }
+
+    /// Draws the border and title, and blanks the inside of the window.
+    pub fn draw(&self, writer: &mut VGAWriter) {
+        let rect = self.rect;
+        if rect.height < 2 || rect.width < 2 {
+            return;
+        }
+        writer.return_to_live();
+
+        let [across, down, top_left, top_right, bottom_left, bottom_right] =
+            self.border.chars();
+        let style = self.border_style;
+        for col in rect.col + 1..rect.last_col() {
+            put(writer, rect.row, col, across, style);
+            put(writer, rect.last_row(), col, across, style);
+        }
+        for row in rect.row + 1..rect.last_row() {
+            put(writer, row, rect.col, down, style);
+            put(writer, row, rect.last_col(), down, style);
+        }
+        put(writer, rect.row, rect.col, top_left, style);
+        put(writer, rect.row, rect.last_col(), top_right, style);
+        put(writer, rect.last_row(), rect.col, bottom_left, style);
+        put(writer, rect.last_row(), rect.last_col(), bottom_right, style);
+
+        // the title needs room for its ends, and a line either side of them
+        if !self.title.is_empty() && rect.width > 6 {
+            let (left, right) = self.border.title_ends();
+            let col = rect.col + 2;
+            put(writer, rect.row, col, left, style);
+            let len = put_str(
+                writer,
+                rect.row,
+                col + 1,
+                rect.width - 6,
+                &self.title,
+                style,
+            );
+            put(writer, rect.row, col + 1 + len, right, style);
+        }
+
+        fill(writer, self.content(), self.style);
+    }
+}
+
+/// A single row of text, usually along the top or bottom of the screen, with
+/// some text at the left and some at the right.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StatusBar {
+    pub row:   usize,
+    pub left:  String,
+    pub right: String,
+    pub style: Style,
+}
+
+impl StatusBar {
+    pub fn new(row: usize) -> Self {
+        StatusBar {
+            row,
+            left: String::new(),
+            right: String::new(),
+            style: Style::INVERTED,
+        }
+    }
+
+    /// Draws the bar across the whole width of the screen. If the two pieces
+    /// of text overlap, the one at the right is drawn over the other.
+    pub fn draw(&self, writer: &mut VGAWriter) {
+        writer.return_to_live();
+        let rect = Rect::new(self.row, 0, 1, BUFFER_WIDTH);
+        fill(writer, rect, self.style);
+        put_str(writer, self.row, 1, BUFFER_WIDTH - 2, &self.left, self.style);
+
+        let len = self.right.chars().count().min(BUFFER_WIDTH - 2);
+        let col = BUFFER_WIDTH - 1 - len;
+        put_str(writer, self.row, col, len, &self.right, self.style);
+    }
+}
+
+/// Lines of text kept in a rectangle, which scroll up as more are added
+/// without disturbing anything else on the screen.
+///
+/// Text is added through `fmt::Write`, and lines that are too long for the
+/// rectangle are wrapped.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScrollRegion {
+    rect:    Rect,
+    style:   Style,
+    /// Only as many lines as fit in the rectangle are kept.
+    lines:   VecDeque<String>,
+    /// The last line, which hasn't been ended yet.
+    partial: String,
+}
+
+impl ScrollRegion {
+    pub fn new(rect: Rect, style: Style) -> Self {
+        ScrollRegion {
+            rect,
+            style,
+            lines: VecDeque::with_capacity(rect.height),
+            partial: String::new(),
+        }
+    }
+
+    pub fn rect(&self) -> Rect {
+        self.rect
+    }
+
+    /// Removes all of the text.
+    pub fn clear(&mut self) {
+        self.lines.clear();
+        self.partial.clear();
+    }
+
+    /// Draws the most recent lines, with the newest at the bottom.
+    pub fn draw(&self, writer: &mut VGAWriter) {
+        writer.return_to_live();
+        fill(writer, self.rect, self.style);
+
+        let partial = Some(&self.partial).filter(|line| !line.is_empty());
+        let lines = self.lines.iter().chain(partial);
+        let skip = (self.lines.len() + partial.is_some() as usize)
+            .saturating_sub(self.rect.height);
+        for (row, line) in (self.rect.row..).zip(lines.skip(skip)) {
+            put_str(
+                writer,
+                row,
+                self.rect.col,
+                self.rect.width,
+                line,
+                self.style,
+            );
+        }
+    }
+
+    fn end_line(&mut self) {
+        if self.rect.is_empty() {
+            self.partial.clear();
+            return;
+        }
+        if self.lines.len() == self.rect.height {
+            self.lines.pop_front();
+        }
+        let line = core::mem::take(&mut self.partial);
+        self.lines.push_back(line);
+    }
+}
+
+impl fmt::Write for ScrollRegion {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for c in s.chars() {
+            match c {
+                '\n' => self.end_line(),
+                c => {
+                    if self.partial.chars().count() >= self.rect.width {
+                        self.end_line();
+                    }
+                    self.partial.push(c);
+                },
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Draws a character, unless it's off the screen.
+fn put(writer: &mut VGAWriter, row: usize, col: usize, c: char, style: Style) {
+    if row < BUFFER_HEIGHT && col < BUFFER_WIDTH {
+        writer.buffer.chars[row][col].write(VGAChar {
+            ascii_character: cp437::from_char_or_replacement(c),
+            color_code:      ColorCode::new(style.fg, style.bg),
+        });
+    }
+}
+
+/// Draws as much of a string as fits in `width` cells, returning the number
+/// of cells drawn.
+fn put_str(
+    writer: &mut VGAWriter, row: usize, col: usize, width: usize, s: &str,
+    style: Style,
+) -> usize {
+    let mut len = 0;
+    for (col, c) in (col..col + width).zip(s.chars()) {
+        put(writer, row, col, c, style);
+        len += 1;
+    }
+    len
+}
+
+/// Blanks a rectangle.
+fn fill(writer: &mut VGAWriter, rect: Rect, style: Style) {
+    for row in rect.row..rect.row + rect.height {
+        for col in rect.col..rect.col + rect.width {
+            put(writer, row, col, ' ', style);
+        }
+    }
+}
+
+
+#[test_case]
+fn test_window() {
+    use alloc::vec::Vec;
+    use core::fmt::Write;
+
+    use super::console;
+
+    let mut writer = console(super::CONSOLE_COUNT - 1).lock();
+    let (top, _) = Rect::SCREEN.split_rows(4);
+    let (rect, _) = top.split_cols(12);
+    let window = Window::new(rect, "test");
+    window.draw(&mut writer);
+
+    let mut region = ScrollRegion::new(window.content(), Style::NORMAL);
+    write!(region, "one\ntwo\nthree-four-five").unwrap();
+    region.draw(&mut writer);
+
+    let row = |writer: &VGAWriter, row: usize| -> Vec<u8> {
+        (0..rect.width)
+            .map(|col| writer.buffer.chars[row][col].read().ascii_character)
+            .collect()
+    };
+    // the box-drawing characters are shown by their code page 437 bytes
+    assert_eq!(row(&writer, 0), b"\xda\xc4\xb4test\xc3\xc4\xc4\xc4\xbf");
+    assert_eq!(row(&writer, 1), b"\xb3three-four\xb3");
+    assert_eq!(row(&writer, 2), b"\xb3-five     \xb3");
+}